@@ -1,23 +1,72 @@
-use glam::IVec2;
+use glam::{BVec2, IVec2, UVec2};
 use std::{
-    collections::{HashMap, HashSet},
-    iter::zip,
+    collections::{BTreeSet, HashMap},
+    fmt::{Debug, Display},
     ops::{Add, Index, IndexMut, Neg},
     slice::Iter,
 };
 
-use crate::tile::{IdMap, TileId};
+use crate::tile::TileId;
 
-#[derive(Debug, Default, Clone)]
-pub struct AdjacencyRules {
-    map: HashMap<usize, [HashSet<usize>; 4]>,
+/// The directions between neighboring cells of a grid, [`CardinalDirs`] for 2D grids and
+/// [`Dir3`](crate::voxel::Dir3) for 3D grids
+///
+/// Also decides how the cells of the grid are laid out, so the solver can work with the
+/// index of a cell and only needs to know the dimension of the grid through this trait
+pub trait Direction:
+    Copy + Eq + Debug + Neg<Output = Self> + Into<usize> + Send + Sync + 'static
+{
+    /// The location of a cell, e.g. `UVec2`
+    type Loc: Copy + PartialEq + Debug + Display + Send + Sync + 'static;
+    /// Whether the grid wraps around on each axis, e.g. `BVec2`
+    type Axes: Copy + Debug + Send + Sync + 'static;
+    /// A value for every direction, indexed by direction
+    type Array<T>: Index<Self, Output = T> + IndexMut<Self> + IntoIterator<Item = T> + AsRef<[T]>;
+
+    /// Every direction, ordered by their index
+    fn all() -> Self::Array<Self>;
+
+    fn array_from_fn<T>(f: impl FnMut(Self) -> T) -> Self::Array<T>;
+
+    /// No axis wraps around
+    fn bounded() -> Self::Axes;
+
+    fn num_cells(dims: Self::Loc) -> usize;
+
+    fn inbounds(loc: Self::Loc, dims: Self::Loc) -> bool;
+
+    /// The index of `loc` in a grid with dimensions `dims`, the x axis changes fastest
+    fn index(loc: Self::Loc, dims: Self::Loc) -> usize;
+
+    /// The inverse of [`Direction::index`]
+    fn loc(index: usize, dims: Self::Loc) -> Self::Loc;
+
+    /// The neighbor of `loc` in this direction, wrapped around the edges of the grid on the
+    /// `periodic` axes. `None` if the neighbor is outside of the grid
+    fn neighbor(self, loc: Self::Loc, dims: Self::Loc, periodic: Self::Axes) -> Option<Self::Loc>;
+}
+
+#[derive(Debug, Clone)]
+pub struct AdjacencyRules<D: Direction = CardinalDirs> {
+    // BTreeSet keeps the iteration order of `enabled_by` stable so the order
+    // removals are propogated in (and therefore the output) is reproducible
+    map: HashMap<usize, D::Array<BTreeSet<usize>>>,
+}
+
+impl<D: Direction> Default for AdjacencyRules<D> {
+    fn default() -> Self {
+        return Self {
+            map: HashMap::new(),
+        };
+    }
 }
 
-impl AdjacencyRules {
+impl<D: Direction> AdjacencyRules<D> {
     pub fn new() -> Self {
         return Self::default();
     }
 
+    /// The number of tiles with an entry, i.e. that are allowed next to some tile
     pub fn len(&self) -> usize {
         return self.map.len();
     }
@@ -26,176 +75,133 @@ impl AdjacencyRules {
         self.len() == 0
     }
 
-    pub fn allow(&mut self, from: usize, to: usize, dir: CardinalDirs) {
+    pub fn allow(&mut self, from: usize, to: usize, dir: D) {
         self.allow_one_way(from, to, dir);
         self.allow_one_way(to, from, -dir);
     }
 
-    fn allow_one_way(&mut self, from: usize, to: usize, dir: CardinalDirs) {
-        self.map.entry(from).or_insert_with(Default::default)[dir].insert(to);
+    fn allow_one_way(&mut self, from: usize, to: usize, dir: D) {
+        self.map
+            .entry(from)
+            .or_insert_with(|| D::array_from_fn(|_| BTreeSet::new()))[dir]
+            .insert(to);
         log::trace!("allowing {from} -> {dir:?} -> {to}");
     }
 
-    pub fn is_allowed(&self, from: usize, to: usize, dir: CardinalDirs) -> bool {
-        return self.map[&from][dir].contains(&to);
+    /// Tiles without an entry aren't allowed next to anything
+    pub fn is_allowed(&self, from: usize, to: usize, dir: D) -> bool {
+        return self
+            .map
+            .get(&from)
+            .is_some_and(|allowed_adjacents| allowed_adjacents[dir].contains(&to));
     }
 
-    pub fn enabled_by(&self, from: TileId, dir: CardinalDirs) -> Vec<TileId> {
+    /// The tiles allowed in `dir` of `from` in ascending order. Empty for tiles without an
+    /// entry
+    pub fn enabled_by(&self, from: TileId, dir: D) -> Vec<TileId> {
         match self.map.get(&from) {
             Some(allowed_adjacents) => allowed_adjacents[dir].iter().copied().collect(),
-            None => panic!("no tile entry for tile {from}")
+            None => Vec::new(),
         }
     }
 
-    fn enabled_by_count(&self, from: usize) -> [usize; 4] {
-        let mut by_dirs: [usize; 4] = [0,0,0,0]; 
-        for &dir in CardinalDirs::iter() {
-            by_dirs[dir] = self.enabled_by(from, dir).len();
-        }
-        return by_dirs;
+    /// Every allowed `(from, to, dir)` triple, each rule appears in both directions
+    pub fn iter(&self) -> impl Iterator<Item = (TileId, TileId, D)> + '_ {
+        return self.map.iter().flat_map(|(&from, by_dir)| {
+            D::all()
+                .into_iter()
+                .flat_map(move |dir| by_dir[dir].iter().map(move |&to| (from, to, dir)))
+        });
     }
 
     #[cfg(test)]
     pub fn allowed_in_all_dirs(&self, from_: usize, to_: usize) -> bool {
-        return CardinalDirs::iter()
-            .map(|&dir| self.is_allowed(from_, to_, dir))
+        return D::all()
+            .into_iter()
+            .map(|dir| self.is_allowed(from_, to_, dir))
             .all(|b| b);
     }
 }
 
-type Enabled = [usize; 4];
-
+/// The immutable form of [`AdjacencyRules`] used while solving
+///
+/// The tiles each tile enables in each direction are stored in a single flat vec so
+/// looking them up doesn't hash or allocate
 #[derive(Debug, Clone)]
-pub struct EnablerDict {
-    enablers: IdMap<Option<Enabled>>,
+pub struct AdjacencyTable<D: Direction = CardinalDirs> {
+    /// where the enabled tiles of each tile and direction start in `enabled`,
+    /// indexed by `tile * num_dirs + dir`
+    offsets: Vec<usize>,
+    enabled: Vec<TileId>,
+    num_dirs: usize,
+    dirs: std::marker::PhantomData<D>,
 }
 
-impl EnablerDict {
-    pub fn new(adjacency_rules: &AdjacencyRules) -> Self {
-        let len = adjacency_rules.map.len();
-        let mut enablers: IdMap<Option<Enabled>> = vec![Default::default(); len];
-        for (id,enabler_counts) in enablers.iter_mut().enumerate() {
-            let counts = adjacency_rules.enabled_by_count(id);
-            *enabler_counts = Some(counts);
-        }
-        return Self { enablers };
-    }
-
-    pub fn remove_single(
-        &mut self,
-        // the tile id of the enabler that may or may not
-        // have been enabling some of our tiles
-        removed_enabler: TileId,
-        // the dir from removed_enabler to us
-        dir: CardinalDirs,
-        adjacency_rules: &AdjacencyRules,
-    ) -> Option<Vec<TileId>> {
-        // enabled is the list of tiles the removed_enabler enables pointing towards us
-        let enabled_by_enabler: Vec<TileId> = adjacency_rules.enabled_by(removed_enabler, dir);
-        let removed_tiles: Vec<TileId> = enabled_by_enabler
-            .iter()
-            .filter_map(|&id| {
-                match &mut self.enablers[id] {
-                    // tile is already removed
-                    // do nothing
-                    None => {
-                        log::trace!("tile {id} already removed");
-                        None
-                    },
-                    // tile has enablers
-                    Some(counts) => {
-                        // count in opposite dir i.e. pointing towards removed_enabler
-                        let count_in_dir = &mut counts[-dir];
-
-                        log::trace!("tile {id} not removed yet, decrementing count: {count_in_dir} in -{dir:?}");
-                        assert!(count_in_dir != &0);
-                        // decrementing this tile would remove all enablers in a dir
-                        // making the this tile impossible
-                        // i.e. this tile is incompatible with one of the neighboring cells
-                        // enabled tiles
-                        if count_in_dir == &1 {
-                            // remove (disable) the tile completely
-                            self.enablers[id] = None;
-                            // save event for change propogation
-                            Some(id)
-                        } else {
-                            // decrement other count
-                            *count_in_dir -= 1;
-                            // no tile TileRemovalEvent
-                            None
-                        }
-                    }
+impl<D: Direction> AdjacencyTable<D> {
+    pub fn new(adjacency_rules: &AdjacencyRules<D>) -> Self {
+        return Self::with_num_tiles(adjacency_rules, adjacency_rules.len());
+    }
+
+    /// A table of the tiles `0..num_tiles`, tiles without an entry in the rules aren't
+    /// allowed next to anything. Panics if the rules allow a tile outside of that range
+    pub fn with_num_tiles(adjacency_rules: &AdjacencyRules<D>, num_tiles: usize) -> Self {
+        let num_dirs = D::all().as_ref().len();
+        let mut offsets = Vec::with_capacity(num_tiles * num_dirs + 1);
+        let mut enabled = Vec::new();
+        offsets.push(0);
+        for id in 0..num_tiles {
+            for dir in D::all() {
+                if let Some(by_dir) = adjacency_rules.map.get(&id) {
+                    // BTreeSet iterates in order so the slices are sorted
+                    enabled.extend(by_dir[dir].iter().copied());
                 }
-            })
-            .collect();
-        if removed_tiles.is_empty() {
-            return None;
-        } else {
-            return Some(removed_tiles);
+                offsets.push(enabled.len());
+            }
         }
+        if let Some(&unknown) = adjacency_rules.map.keys().find(|&&id| id >= num_tiles) {
+            panic!("the adjacency rules have an entry for tile {unknown} but there are only {num_tiles} tiles");
+        }
+        return Self {
+            offsets,
+            enabled,
+            num_dirs,
+            dirs: std::marker::PhantomData,
+        };
     }
 
-    // TODO: remove unused functions and make sure the ones that are used 
-    // aren't doing unnecessary clones
+    /// The number of tiles
+    pub fn len(&self) -> usize {
+        return self.offsets.len() / self.num_dirs;
+    }
 
-    pub fn allowed_tile_ids(&self) -> impl Iterator<Item = TileId> + '_ {
-        return self
-            .enablers
-            .iter()
-            .enumerate()
-            .filter_map(|(idx, b)| b.map(|_| idx));
-    }
-
-    pub fn filter_allowed<'a, T>(&'a self, other: &'a Vec<T>) -> impl Iterator<Item = &'a T> + '_
-    where
-        T: Clone,
-    {
-        assert_eq!(self.enablers.len(), other.len());
-        return self.iter_allowed(other).flatten();
-    }
-
-    pub fn iter_allowed<'a, T>(&'a self, other: &'a Vec<T>) -> impl Iterator<Item = Option<&'a T>> + '_
-    where
-        T: Clone,
-    {
-        assert_eq!(self.enablers.len(), other.len());
-        return zip(&self.enablers, other).map(|(b, v)| b.map(|_| v));
-    }
-
-    pub fn filter_allowed_enumerate<'a, T>(
-        &'a self,
-        other: &'a Vec<T>,
-    ) -> impl Iterator<Item = (TileId, T)> + '_
-    where
-        T: Clone,
-    {
-        assert_eq!(self.enablers.len(), other.len());
-        return zip(&self.enablers, other)
-            .enumerate()
-            .filter_map(|(i, (&b, v))| b.map(|_| (i, v.clone())));
-    }
-
-
-    /// Remove all enabled/allowable/possible tiles except one (the lone survivor!)
-    pub fn remove_all_but(&mut self, marcus_luttrell: TileId) -> Vec<TileId> {
-        let mut removed_tile_ids = Vec::new();
-        for (id, b) in self.enablers.iter_mut().enumerate() {
-            if id == marcus_luttrell {
-                if b.is_none() {
-                    unreachable!(
-                        "Contradiction: lone survivor Tile `{}` already not allowed",
-                        id
-                    );
-                }
-            } else if b.is_some() {
-                *b = None;
-                removed_tile_ids.push(id);
-            }
-        }
-        return removed_tile_ids;
+    pub fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /// The tiles allowed in `dir` of `from` in ascending order
+    #[inline]
+    pub fn enabled_by(&self, from: TileId, dir: D) -> &[TileId] {
+        let i = from * self.num_dirs + dir.into();
+        return &self.enabled[self.offsets[i]..self.offsets[i + 1]];
+    }
+
+    pub fn is_allowed(&self, from: TileId, to: TileId, dir: D) -> bool {
+        return self.enabled_by(from, dir).binary_search(&to).is_ok();
+    }
+
+    /// The number of tiles enabling `id` from each direction
+    pub fn enabler_counts(&self, id: TileId) -> D::Array<usize> {
+        return D::array_from_fn(|dir| self.enabled_by(id, dir).len());
     }
 }
-#[derive(Debug, Clone, Copy)]
+
+impl<D: Direction> From<&AdjacencyRules<D>> for AdjacencyTable<D> {
+    fn from(adjacency_rules: &AdjacencyRules<D>) -> Self {
+        return Self::new(adjacency_rules);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CardinalDirs {
     Up,
     Left,
@@ -288,6 +294,53 @@ impl<T> IndexMut<CardinalDirs> for [T; 4] {
     }
 }
 
+impl Direction for CardinalDirs {
+    type Loc = UVec2;
+    type Axes = BVec2;
+    type Array<T> = [T; 4];
+
+    fn all() -> [Self; 4] {
+        return Self::as_array();
+    }
+
+    fn array_from_fn<T>(f: impl FnMut(Self) -> T) -> [T; 4] {
+        return Self::as_array().map(f);
+    }
+
+    fn bounded() -> BVec2 {
+        return BVec2::FALSE;
+    }
+
+    fn num_cells(dims: UVec2) -> usize {
+        return dims.x as usize * dims.y as usize;
+    }
+
+    fn inbounds(loc: UVec2, dims: UVec2) -> bool {
+        return loc.cmplt(dims).all();
+    }
+
+    #[inline]
+    fn index(loc: UVec2, dims: UVec2) -> usize {
+        return (loc.y * dims.x + loc.x) as usize;
+    }
+
+    fn loc(index: usize, dims: UVec2) -> UVec2 {
+        let index = index as u32;
+        return UVec2::new(index % dims.x, index / dims.x);
+    }
+
+    fn neighbor(self, loc: UVec2, dims: UVec2, periodic: BVec2) -> Option<UVec2> {
+        let dims = dims.as_ivec2();
+        let neighbor = self + loc.as_ivec2();
+        let wrapped = IVec2::new(neighbor.x.rem_euclid(dims.x), neighbor.y.rem_euclid(dims.y));
+        let neighbor = IVec2::select(periodic, wrapped, neighbor);
+        if neighbor.cmplt(IVec2::ZERO).any() || neighbor.cmpge(dims).any() {
+            return None;
+        }
+        return Some(neighbor.as_uvec2());
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -296,10 +349,7 @@ mod test {
     use CardinalDirs::*;
 
     fn simple_patterns_common() -> AdjacencyRules {
-        return construct_simple_patterns()
-            .wfc_data
-            .unwrap()
-            .adjacency_rules;
+        return construct_simple_patterns().adjacency_rules;
     }
 
     #[test]
@@ -408,13 +458,41 @@ mod test {
     }
 
     #[test]
-    fn remove_all_but_one_enabler() {
+    fn table_matches_rules() {
         let adj = simple_patterns_common();
-        let mut enab = EnablerDict::new(&adj);
-        let mut removed_ids = enab.remove_all_but(BLANK);
-        assert!(removed_ids.len() == 4);
-        removed_ids.sort();
-        assert!(removed_ids == vec![1, 2, 3, 4]);
+        let table = AdjacencyTable::new(&adj);
+        assert_eq!(table.len(), 5);
+        for from in 0..5 {
+            for dir in CardinalDirs::as_array() {
+                assert_eq!(
+                    table.enabled_by(from, dir),
+                    adj.enabled_by(from, dir).as_slice()
+                );
+                for to in 0..5 {
+                    assert_eq!(table.is_allowed(from, to, dir), adj.is_allowed(from, to, dir));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tiles_without_an_entry_are_not_allowed_next_to_anything() {
+        let adj = simple_patterns_common();
+        assert!(!adj.is_allowed(5, BLANK, Up));
+        assert!(adj.enabled_by(5, Up).is_empty());
+        let table = AdjacencyTable::with_num_tiles(&adj, 6);
+        assert_eq!(table.len(), 6);
+        assert_eq!(table.enabler_counts(5), [0; 4]);
+        assert_eq!(
+            table.enabled_by(BLANK, Up),
+            adj.enabled_by(BLANK, Up).as_slice()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "only 4 tiles")]
+    fn table_panics_on_rules_for_missing_tiles() {
+        AdjacencyTable::with_num_tiles(&simple_patterns_common(), 4);
     }
 
     fn usize4_to_vec4(arr: [usize; 4]) -> UVec4 {
@@ -423,14 +501,9 @@ mod test {
     }
 
     fn enabler_counts_common() -> Vec<[usize;4]> {
-        let adj = simple_patterns_common();
-        let enab = EnablerDict::new(&adj);
-        let counts: Vec<[usize; 4]> = enab
-            .enablers
-            .iter()
-            .map(|opt_c| {
-                opt_c.expect("All enabler counts should start out as Some(count)")
-            })
+        let table = AdjacencyTable::new(&simple_patterns_common());
+        let counts: Vec<[usize; 4]> = (0..table.len())
+            .map(|id| table.enabler_counts(id))
             .collect();
         return counts;
     }
@@ -471,9 +544,9 @@ mod test {
 
     #[test]
     fn no_enabler_counts_are_zero() {
-        let adj = simple_patterns_common();
+        let table = AdjacencyTable::new(&simple_patterns_common());
         for id in 0..5 {
-            let counts = adj.enabled_by_count(id);
+            let counts = table.enabler_counts(id);
             let v4 = usize4_to_vec4(counts);
             let b4 = v4.cmpeq(UVec4::ZERO);
             assert!(!b4.any());
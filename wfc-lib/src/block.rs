@@ -0,0 +1,233 @@
+//! Solving very large outputs by modifying them in blocks
+
+use glam::{IVec2, UVec2};
+
+use crate::{
+    adjacency_rules::{AdjacencyRules, CardinalDirs},
+    tile::{TileId, Weight},
+    wfc::{mix_seed, Backtracking, Model, WfcError},
+    Area, Grid,
+};
+
+/// Solves outputs too large for a single [`Model`] by modifying them in blocks, as in
+/// Paul Merrell's model synthesis
+///
+/// The output starts filled with a tile that is allowed next to itself on every side, so it
+/// is consistent from the start. The solver then sweeps over the output in overlapping
+/// blocks, clearing each block and solving it with a block sized [`Model`] against the
+/// fixed cells around it. A block that can't be solved keeps its previous tiles, so the
+/// output is always consistent and a contradiction only ever costs one block. Only one
+/// block is held in a [`Model`] at a time
+#[derive(Debug, Clone)]
+pub struct BlockSolver {
+    adjacency_rules: AdjacencyRules,
+    tile_frequencies: Vec<Weight>,
+    dims: UVec2,
+    block_size: UVec2,
+    seed: u64,
+    max_attempts: u32,
+    tiles: Vec<TileId>,
+    /// the index of the next block in the sweep
+    next_block: usize,
+    /// the number of blocks solved or failed so far, used to derive their seeds
+    num_solves: u64,
+}
+
+impl BlockSolver {
+    pub fn new(
+        adjacency_rules: AdjacencyRules,
+        tile_frequencies: Vec<Weight>,
+        dims: UVec2,
+        initial_tile: TileId,
+        seed: u64,
+    ) -> Self {
+        assert!(
+            CardinalDirs::iter().all(|&dir| adjacency_rules.is_allowed(
+                initial_tile,
+                initial_tile,
+                dir
+            )),
+            "tile {initial_tile} must be allowed next to itself on every side"
+        );
+        return Self {
+            adjacency_rules,
+            tile_frequencies,
+            dims,
+            block_size: dims.min(UVec2::splat(16)),
+            seed,
+            max_attempts: 5,
+            tiles: vec![initial_tile; Grid(dims).area() as usize],
+            next_block: 0,
+            num_solves: 0,
+        };
+    }
+
+    /// The size of the blocks in cells, clamped to the output. Defaults to 16x16
+    pub fn with_block_size(mut self, block_size: UVec2) -> Self {
+        assert!(block_size.cmpgt(UVec2::ZERO).all(), "blocks can't be empty");
+        self.block_size = block_size.min(self.dims);
+        self.next_block = 0;
+        return self;
+    }
+
+    /// The number of times a block is restarted before it keeps its previous tiles.
+    /// Defaults to 5
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        return self;
+    }
+
+    pub fn dims(&self) -> UVec2 {
+        return self.dims;
+    }
+
+    /// The tiles of the output in row major order
+    pub fn tiles(&self) -> &[TileId] {
+        return &self.tiles;
+    }
+
+    pub fn tile_at(&self, loc: UVec2) -> TileId {
+        return self.tiles[(loc.y * self.dims.x + loc.x) as usize];
+    }
+
+    /// The number of blocks in one sweep over the output
+    pub fn num_blocks(&self) -> usize {
+        let counts = self.block_counts();
+        return (counts.x * counts.y) as usize;
+    }
+
+    /// Clears and solves the next block of the sweep, returning its bounds and whether it was
+    /// solved. The tiles of a block that can't be solved are left as they were
+    pub fn step(&mut self) -> (UVec2, UVec2, bool) {
+        let min = self.block_min(self.next_block);
+        let max = min + self.block_size;
+        self.next_block = (self.next_block + 1) % self.num_blocks();
+        let seed = mix_seed(self.seed, self.num_solves);
+        self.num_solves += 1;
+
+        let solved = match self.solve_block(min, seed) {
+            Ok(tiles) => {
+                for (offset, tile) in Grid(self.block_size).iter_locs().zip(tiles) {
+                    let loc = min + offset;
+                    self.tiles[(loc.y * self.dims.x + loc.x) as usize] = tile;
+                }
+                true
+            }
+            Err(err) => {
+                log::info!("block {min} - {max} kept its tiles: {err}");
+                false
+            }
+        };
+        return (min, max, solved);
+    }
+
+    /// Sweeps over the output until every block of a sweep is solved, up to `max_sweeps`
+    /// times. Sweeps always start from the first block
+    ///
+    /// Returns the number of sweeps, or AttemptsExhausted if some block of every sweep kept
+    /// its previous tiles. The output is consistent either way
+    pub fn run(&mut self, max_sweeps: u32) -> Result<u32, WfcError> {
+        self.next_block = 0;
+        for sweep in 0..max_sweeps {
+            let mut all_solved = true;
+            for _ in 0..self.num_blocks() {
+                let (_, _, solved) = self.step();
+                all_solved &= solved;
+            }
+            if all_solved {
+                return Ok(sweep + 1);
+            }
+        }
+        return Err(WfcError::AttemptsExhausted {
+            attempts: max_sweeps,
+        });
+    }
+
+    /// The number of blocks along each axis. Neighboring blocks overlap by half a block
+    fn block_counts(&self) -> UVec2 {
+        let stride = self.block_stride();
+        return (self.dims - self.block_size + stride - UVec2::ONE) / stride + UVec2::ONE;
+    }
+
+    fn block_stride(&self) -> UVec2 {
+        return (self.block_size / 2).max(UVec2::ONE);
+    }
+
+    /// The top left cell of the block at `index` in the sweep. The last blocks along each
+    /// axis are moved back so they end at the edge of the output
+    fn block_min(&self, index: usize) -> UVec2 {
+        let counts = self.block_counts();
+        let block = UVec2::new(index as u32 % counts.x, index as u32 / counts.x);
+        return (block * self.block_stride()).min(self.dims - self.block_size);
+    }
+
+    /// Solves the block at `min` against the cells around it
+    fn solve_block(&self, min: UVec2, seed: u64) -> Result<Vec<TileId>, WfcError> {
+        let dims = self.dims.as_ivec2();
+        let outside = |offset: IVec2| {
+            let loc = min.as_ivec2() + offset;
+            if loc.cmplt(IVec2::ZERO).any() || loc.cmpge(dims).any() {
+                return None;
+            }
+            return Some(self.tile_at(loc.as_uvec2()));
+        };
+        return Model::from_seed(
+            self.adjacency_rules.clone(),
+            self.tile_frequencies.clone(),
+            self.block_size,
+            seed,
+        )
+        .with_backtracking(Backtracking::default())
+        .solve_subregion(outside, seed, self.max_attempts);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::simple_patterns::{assert_follows_rules, construct_simple_patterns, BLANK};
+
+    fn solver(dims: UVec2, seed: u64) -> BlockSolver {
+        let data = construct_simple_patterns();
+        return BlockSolver::new(
+            data.adjacency_rules,
+            data.tile_frequencies,
+            dims,
+            BLANK,
+            seed,
+        )
+        .with_block_size(UVec2::splat(8));
+    }
+
+    #[test]
+    fn blocks_cover_the_output() {
+        let solver = solver(UVec2::new(20, 13), 0);
+        let mut covered = vec![false; 20 * 13];
+        for index in 0..solver.num_blocks() {
+            let min = solver.block_min(index);
+            assert!((min + solver.block_size).cmple(solver.dims).all());
+            for loc in Grid(solver.block_size).iter_locs() {
+                let loc = min + loc;
+                covered[(loc.y * 20 + loc.x) as usize] = true;
+            }
+        }
+        assert!(covered.into_iter().all(|covered| covered));
+    }
+
+    #[test]
+    fn large_output_is_consistent() {
+        let dims = UVec2::splat(48);
+        let mut solver = solver(dims, 2);
+        solver.run(4).unwrap();
+        let rules = construct_simple_patterns().adjacency_rules;
+        let locs = Grid(dims).iter_locs().map(|loc| loc.as_ivec2());
+        assert_follows_rules(&rules, locs, |loc| {
+            if loc.cmpge(dims.as_ivec2()).any() {
+                return None;
+            }
+            return Some(solver.tile_at(loc.as_uvec2()));
+        });
+        // the blocks actually changed the output
+        assert!(solver.tiles().iter().any(|&tile| tile != BLANK));
+    }
+}
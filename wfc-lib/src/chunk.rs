@@ -0,0 +1,236 @@
+//! Generating an unbounded world one chunk at a time
+
+use glam::{IVec2, UVec2};
+use std::collections::HashMap;
+
+use crate::{
+    adjacency_rules::AdjacencyRules,
+    tile::{TileId, Weight},
+    wfc::{mix_seed, Backtracking, Model, WfcError},
+    Area, Grid,
+};
+
+/// Generates a world of fixed size chunks on demand, e.g. as the player moves around
+///
+/// Each chunk is solved with its own [`Model`] whose edge cells are constrained by the
+/// tiles of the chunks already generated next to it, so the tiles match across seams.
+/// The seed of a chunk is derived from the world seed and the chunk coordinate, so a chunk
+/// generated with the same neighbors is always the same regardless of when it is generated.
+///
+/// When a chunk can't be solved against its neighbors it is repaired by solving it again
+/// together with the cells of its neighbors within `repair_margin` of the seams, which
+/// changes those cells of the neighbors
+#[derive(Debug, Clone)]
+pub struct ChunkGenerator {
+    adjacency_rules: AdjacencyRules,
+    tile_frequencies: Vec<Weight>,
+    chunk_size: UVec2,
+    world_seed: u64,
+    max_attempts: u32,
+    repair_margin: u32,
+    chunks: HashMap<IVec2, Vec<TileId>>,
+}
+
+impl ChunkGenerator {
+    pub fn new(
+        adjacency_rules: AdjacencyRules,
+        tile_frequencies: Vec<Weight>,
+        chunk_size: UVec2,
+        world_seed: u64,
+    ) -> Self {
+        assert!(chunk_size.cmpgt(UVec2::ZERO).all(), "chunks can't be empty");
+        return Self {
+            adjacency_rules,
+            tile_frequencies,
+            chunk_size,
+            world_seed,
+            max_attempts: 20,
+            repair_margin: 2,
+            chunks: HashMap::new(),
+        };
+    }
+
+    /// The number of times a chunk is restarted before it is repaired, and the repair is
+    /// restarted before giving up. Defaults to 20
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        return self;
+    }
+
+    /// How many cells into its neighbors a chunk that can't be solved is regenerated.
+    /// Defaults to 2, 0 disables repairing
+    pub fn with_repair_margin(mut self, repair_margin: u32) -> Self {
+        self.repair_margin = repair_margin;
+        return self;
+    }
+
+    pub fn chunk_size(&self) -> UVec2 {
+        return self.chunk_size;
+    }
+
+    /// The tiles of the chunk at `coord` in row major order, if it has been generated
+    pub fn chunk(&self, coord: IVec2) -> Option<&[TileId]> {
+        return self.chunks.get(&coord).map(Vec::as_slice);
+    }
+
+    /// The tile at `loc` in world cells, if its chunk has been generated
+    pub fn tile_at(&self, loc: IVec2) -> Option<TileId> {
+        let (coord, index) = self.split_loc(loc);
+        return self.chunks.get(&coord).map(|tiles| tiles[index]);
+    }
+
+    /// Forgets the chunk at `coord`, returning its tiles. The chunk is generated again,
+    /// possibly differently, the next time it is requested
+    pub fn unload(&mut self, coord: IVec2) -> Option<Vec<TileId>> {
+        return self.chunks.remove(&coord);
+    }
+
+    /// Generates the chunk at `coord` if it hasn't been generated yet
+    ///
+    /// Returns the coordinates of every chunk whose tiles were set or changed, starting with
+    /// `coord`. This only includes other chunks if the new chunk had to be repaired, and is
+    /// empty if the chunk already existed
+    pub fn generate(&mut self, coord: IVec2) -> Result<Vec<IVec2>, WfcError> {
+        if self.chunks.contains_key(&coord) {
+            return Ok(Vec::new());
+        }
+        let seed = self.chunk_seed(coord);
+        let min = coord * self.chunk_size.as_ivec2();
+        let err = match self.solve_region(min, self.chunk_size, seed) {
+            Ok(tiles) => {
+                self.chunks.insert(coord, tiles);
+                return Ok(vec![coord]);
+            }
+            Err(err) => err,
+        };
+        if self.repair_margin == 0 {
+            return Err(err);
+        }
+        log::info!("chunk {coord} can't be solved against its neighbors, repairing it: {err}");
+
+        let margin = IVec2::splat(self.repair_margin as i32);
+        let repair_min = min - margin;
+        let repair_size = self.chunk_size + 2 * self.repair_margin;
+        let tiles = self.solve_region(repair_min, repair_size, mix_seed(seed, 1))?;
+
+        let mut changed = vec![coord];
+        self.chunks
+            .insert(coord, vec![0; Grid(self.chunk_size).area() as usize]);
+        for (loc, tile) in Grid(repair_size).iter_locs().zip(tiles) {
+            let loc = repair_min + loc.as_ivec2();
+            let (chunk_coord, _) = self.split_loc(loc);
+            // cells of chunks that don't exist yet only gave the repair some slack
+            if !self.set_tile(loc, tile) {
+                continue;
+            }
+            if !changed.contains(&chunk_coord) {
+                changed.push(chunk_coord);
+            }
+        }
+        return Ok(changed);
+    }
+
+    /// The seed of the first attempt at the chunk at `coord`
+    fn chunk_seed(&self, coord: IVec2) -> u64 {
+        let packed = ((coord.x as u32 as u64) << 32) | coord.y as u32 as u64;
+        return mix_seed(self.world_seed, packed);
+    }
+
+    /// Sets the tile at `loc` in world cells if its chunk exists, returning whether it
+    /// changed
+    fn set_tile(&mut self, loc: IVec2, tile: TileId) -> bool {
+        let (coord, index) = self.split_loc(loc);
+        let Some(tiles) = self.chunks.get_mut(&coord) else {
+            return false;
+        };
+        return std::mem::replace(&mut tiles[index], tile) != tile;
+    }
+
+    /// The coordinate of the chunk containing `loc` in world cells and the index of `loc`
+    /// in its tiles
+    fn split_loc(&self, loc: IVec2) -> (IVec2, usize) {
+        let size = self.chunk_size.as_ivec2();
+        let coord = IVec2::new(loc.x.div_euclid(size.x), loc.y.div_euclid(size.y));
+        let local = loc - coord * size;
+        return (coord, (local.y * size.x + local.x) as usize);
+    }
+
+    /// Solves the cells from `min` to `min + size` in world cells, with the cells on its
+    /// edges only allowing the tiles the generated cells around it allow
+    fn solve_region(&self, min: IVec2, size: UVec2, seed: u64) -> Result<Vec<TileId>, WfcError> {
+        return Model::from_seed(
+            self.adjacency_rules.clone(),
+            self.tile_frequencies.clone(),
+            size,
+            seed,
+        )
+        .with_backtracking(Backtracking::default())
+        .solve_subregion(|loc| self.tile_at(min + loc), seed, self.max_attempts);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::simple_patterns::{assert_follows_rules, construct_simple_patterns};
+
+    fn generator(world_seed: u64) -> ChunkGenerator {
+        let data = construct_simple_patterns();
+        return ChunkGenerator::new(
+            data.adjacency_rules,
+            data.tile_frequencies,
+            UVec2::splat(6),
+            world_seed,
+        );
+    }
+
+    fn generate_area(generator: &mut ChunkGenerator) {
+        // spiral-ish order so chunks are generated against one, two and three neighbors
+        let coords = [
+            (0, 0),
+            (1, 0),
+            (1, 1),
+            (0, 1),
+            (-1, 1),
+            (-1, 0),
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+        ];
+        for (x, y) in coords {
+            generator.generate(IVec2::new(x, y)).unwrap();
+        }
+    }
+
+    #[test]
+    fn seams_are_consistent() {
+        let mut generator = generator(7);
+        generate_area(&mut generator);
+        let rules = construct_simple_patterns().adjacency_rules;
+        let locs: Vec<IVec2> = (-6..12)
+            .flat_map(|y| (-6..12).map(move |x| IVec2::new(x, y)))
+            .collect();
+        assert!(locs.iter().all(|&loc| generator.tile_at(loc).is_some()));
+        assert_follows_rules(&rules, locs, |loc| generator.tile_at(loc));
+    }
+
+    #[test]
+    fn chunks_are_deterministic() {
+        let mut a = generator(3);
+        let mut b = generator(3);
+        generate_area(&mut a);
+        generate_area(&mut b);
+        assert_eq!(a.chunks, b.chunks);
+
+        // the same chunk with no neighbors is the same no matter when it is generated
+        let coord = IVec2::new(10, -4);
+        let mut c = generator(3);
+        c.generate(coord).unwrap();
+        a.generate(coord).unwrap();
+        assert_eq!(a.chunk(coord), c.chunk(coord));
+
+        let mut d = generator(4);
+        d.generate(coord).unwrap();
+        assert_ne!(d.chunk(coord), c.chunk(coord));
+    }
+}
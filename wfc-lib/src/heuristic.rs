@@ -0,0 +1,81 @@
+//! Heuristics for choosing which cell the model collapses next,
+//! see [`Model::with_heuristic`](crate::wfc::Model::with_heuristic)
+
+use glam::UVec2;
+use std::fmt::Debug;
+
+use crate::wfc::Cell;
+
+/// Picks the order cells are collapsed in
+///
+/// Every uncollapsed cell is given a priority and the one with the lowest priority is
+/// collapsed next. The priority is recomputed whenever the possible tiles of a cell
+/// change. [`Cell::random_entropy`] is a random value in `[0, 1)` that is redrawn
+/// from the model's rng on every reset, it can be used to break ties
+///
+/// `L` is the location of a cell, the heuristics that don't look at the location work on
+/// grids of any dimension
+pub trait CellHeuristic<L = UVec2>: Debug + Send + Sync {
+    fn priority(&self, cell: &Cell<L>, dims: L) -> f32;
+}
+
+/// Collapses the cell with the lowest shannon entropy first. This is the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinEntropy;
+
+impl<L> CellHeuristic<L> for MinEntropy {
+    fn priority(&self, cell: &Cell<L>, _dims: L) -> f32 {
+        return cell.probability_dict.entropy() + cell.random_entropy;
+    }
+}
+
+/// Collapses the cell with the fewest possible tiles first, ignoring their weights
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MinRemainingValues;
+
+impl<L> CellHeuristic<L> for MinRemainingValues {
+    fn priority(&self, cell: &Cell<L>, _dims: L) -> f32 {
+        return cell.num_possible() as f32 + cell.random_entropy;
+    }
+}
+
+/// Collapses the cells row by row, left to right and top to bottom
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Scanline;
+
+impl CellHeuristic for Scanline {
+    fn priority(&self, cell: &Cell, dims: UVec2) -> f32 {
+        return (cell.loc.y * dims.x + cell.loc.x) as f32;
+    }
+}
+
+/// Collapses the cells in a random order
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomOrder;
+
+impl<L> CellHeuristic<L> for RandomOrder {
+    fn priority(&self, cell: &Cell<L>, _dims: L) -> f32 {
+        return cell.random_entropy;
+    }
+}
+
+/// Grows the output outwards from `point`, collapsing the closest cells first
+///
+/// The distances are jittered by less than a cell so the edge of the grown
+/// area isn't a perfect circle
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DistanceFrom {
+    pub point: UVec2,
+}
+
+impl DistanceFrom {
+    pub fn new(point: UVec2) -> Self {
+        return Self { point };
+    }
+}
+
+impl CellHeuristic for DistanceFrom {
+    fn priority(&self, cell: &Cell, _dims: UVec2) -> f32 {
+        return cell.loc.as_vec2().distance(self.point.as_vec2()) + cell.random_entropy;
+    }
+}
@@ -0,0 +1,149 @@
+//! Filling in the masked part of an existing output
+
+use glam::{IVec2, UVec2};
+use image::{GrayImage, RgbaImage};
+
+use crate::{
+    preprocessor::{Pattern, WfcData},
+    tile::TileId,
+    wfc::{Model, WfcError},
+};
+
+/// Constrains the cells of `model` so its output matches `image` everywhere `mask` is
+/// black, so that solving the model only fills in the white parts of the mask
+///
+/// Cells whose patterns are covered by known pixels only allow the matching pattern, the
+/// others lose the patterns that disagree with the known pixels they overlap. `image` and
+/// `mask` must be the size of the output in pixels, i.e. the model's dimensions times
+/// `data.cell_size`. The constraints are kept when the model is reset.
+///
+/// Returns a Contradiction if no pattern agrees with the known pixels of a cell
+pub fn inpaint(
+    model: &mut Model,
+    data: &WfcData,
+    image: &RgbaImage,
+    mask: &GrayImage,
+) -> Result<(), WfcError> {
+    let output_size = model.dims() * data.cell_size;
+    assert_eq!(
+        UVec2::from(image.dimensions()),
+        output_size,
+        "image must match the output"
+    );
+    assert_eq!(
+        UVec2::from(mask.dimensions()),
+        output_size,
+        "mask must match the output"
+    );
+
+    let pattern_ids = data.pattern_ids();
+    let tile_size = data.tile_size;
+    let num_pixels = (tile_size.x * tile_size.y) as usize;
+    let mut known: Vec<Option<[u8; 4]>> = Vec::with_capacity(num_pixels);
+    // the removals of every cell are propogated together at the end
+    let mut cells: Vec<(UVec2, Vec<TileId>)> = Vec::new();
+
+    for loc in crate::Grid(model.dims()).iter_locs() {
+        // the known pixels under the pattern of the cell, in the same order as a pattern
+        known.clear();
+        let origin = (loc * data.cell_size).as_ivec2();
+        for y in 0..tile_size.y as i32 {
+            for x in 0..tile_size.x as i32 {
+                known.push(known_pixel(image, mask, origin + IVec2::new(x, y)));
+            }
+        }
+        if known.iter().all(Option::is_none) {
+            continue;
+        }
+        if known.iter().all(Option::is_some) {
+            let pattern: Pattern = known.iter().map(|pixel| pixel.unwrap()).collect();
+            match pattern_ids.get(&pattern) {
+                Some(&id) => cells.push((loc, vec![id])),
+                None => {
+                    log::warn!("no pattern matches the known pixels of the cell at {loc}");
+                    return Err(WfcError::Contradiction { loc });
+                }
+            }
+            continue;
+        }
+        let agreeing = data.patterns.iter().enumerate().filter(|(_, pattern)| {
+            return pattern
+                .iter()
+                .zip(&known)
+                .all(|(pixel, known)| known.filter(|known| known != pixel).is_none());
+        });
+        cells.push((loc, agreeing.map(|(id, _)| id).collect()));
+    }
+    return model.restrict_cells(cells);
+}
+
+/// The pixel of the image at `loc` if it is inside the image and not masked
+fn known_pixel(image: &RgbaImage, mask: &GrayImage, loc: IVec2) -> Option<[u8; 4]> {
+    let (width, height) = image.dimensions();
+    if loc.x < 0 || loc.y < 0 || loc.x as u32 >= width || loc.y as u32 >= height {
+        return None;
+    }
+    let (x, y) = (loc.x as u32, loc.y as u32);
+    // anything but black is filled in
+    if mask.get_pixel(x, y)[0] != 0 {
+        return None;
+    }
+    return Some(image.get_pixel(x, y).0);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::simple_patterns::construct_simple_patterns;
+    use image::Luma;
+
+    fn render(model: &Model, data: &WfcData) -> RgbaImage {
+        let output_size = model.dims() * data.cell_size;
+        let mut image = RgbaImage::new(output_size.x, output_size.y);
+        for cell in model.iter_cells() {
+            let pattern = &data.patterns[cell.collapsed_to.unwrap()];
+            for (i, pixel) in pattern.iter().enumerate() {
+                let offset = UVec2::new(i as u32 % data.tile_size.x, i as u32 / data.tile_size.x);
+                let loc = cell.loc * data.cell_size + offset;
+                image.put_pixel(loc.x, loc.y, image::Rgba(*pixel));
+            }
+        }
+        return image;
+    }
+
+    #[test]
+    fn known_cells_are_kept() {
+        let dims = UVec2::splat(10);
+        let data = construct_simple_patterns();
+        let mut original = Model::from_seed(
+            data.adjacency_rules.clone(),
+            data.tile_frequencies.clone(),
+            dims,
+            0,
+        );
+        original.solve(0, 50).unwrap();
+        let image = render(&original, &data);
+
+        // fill in the middle of the output
+        let in_hole =
+            |loc: UVec2| loc.cmpge(UVec2::splat(3)).all() && loc.cmplt(UVec2::splat(7)).all();
+        let mask = GrayImage::from_fn(image.width(), image.height(), |x, y| {
+            let cell = UVec2::new(x, y) / data.cell_size;
+            Luma([if in_hole(cell) { 255 } else { 0 }])
+        });
+        let mut model = Model::from_seed(
+            data.adjacency_rules.clone(),
+            data.tile_frequencies.clone(),
+            dims,
+            1,
+        );
+        inpaint(&mut model, &data, &image, &mask).unwrap();
+        model.solve(1, 50).unwrap();
+        for cell in model.iter_cells() {
+            if !in_hole(cell.loc) {
+                let original_tile = original.get_cell(cell.loc).unwrap().collapsed_to;
+                assert_eq!(cell.collapsed_to, original_tile);
+            }
+        }
+    }
+}
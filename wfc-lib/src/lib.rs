@@ -1,8 +1,14 @@
 pub mod adjacency_rules;
+pub mod block;
+pub mod chunk;
+pub mod heuristic;
+pub mod inpaint;
 pub mod preprocessor;
 pub mod tile;
 pub mod wfc;
 pub mod utils;
+pub mod voxel;
+pub mod weights;
 
 use derive_more::{Deref, DerefMut, From};
 use glam::UVec2;
@@ -90,94 +96,113 @@ pub fn blend_rgba(a: [u8; 4], b: [u8; 4], factor: f32) -> [u8; 4] {
     ]
     .map(rgba_f32_to_u8);
 }
-// pub mod simple_patterns {
-//     use super::*;
-//     use adjacency_rules::CardinalDirs;
-//     use CardinalDirs::*;
-//     pub const CHARS: [&str; 5] = ["' '", "┓", "┛", "┏", "┗"];
-//
-//     const PRINT_CREATION: bool = false;
-//
-//     fn allow_all(
-//         aaa: [usize; 2],
-//         bbb: [usize; 2],
-//         dir: CardinalDirs,
-//         adjacency_rules: &mut AdjacencyRules,
-//     ) {
-//         for a in aaa {
-//             for b in bbb {
-//                 adjacency_rules.allow(a, b, dir);
-//                 if PRINT_CREATION {
-//                     let ac = CHARS[a];
-//                     let bc = CHARS[b];
-//                     println!("Allowing:");
-//                     match dir {
-//                         Up => {
-//                             println!("{}", bc);
-//                             println!("{}", ac);
-//                         }
-//                         Down => {
-//                             println!("{}", ac);
-//                             println!("{}", bc);
-//                         }
-//                         Left => {
-//                             println!("{}{}", bc, ac)
-//                         }
-//                         Right => {
-//                             println!("{}{}", ac, bc)
-//                         }
-//                     }
-//                 }
-//             }
-//         }
-//     }
-//
-//     pub const BLANK: usize = 0; //' '
-//     pub const DL: usize = 1; // ┓
-//     pub const LU: usize = 2; // ┛
-//     pub const RD: usize = 3; // ┏
-//     pub const UR: usize = 4; // ┗
-//
-//     // ┓ ┛
-//     pub const BLANK_RIGHT: [usize; 2] = [DL, LU];
-//     // ┏ ┗
-//     pub const BLANK_LEFT: [usize; 2] = [RD, UR];
-//     // ┏ ┓
-//     pub const BLANK_UP: [usize; 2] = [RD, DL];
-//     // ┗ ┛
-//     pub const BLANK_DOWN: [usize; 2] = [UR, LU];
-//
-//     pub const B2: [usize; 2] = [BLANK, BLANK];
-//
-//     pub fn construct_simple_patterns() -> Wfc {
-//         let mut adjacency_rules = AdjacencyRules::new();
-//         let paths: IdMap<String> = vec!["blank", "dl", "lu", "rd", "ur"]
-//             .iter()
-//             .map(|&name| format!("./inputs/simple/{}.png", name))
-//             .collect();
-//         let tile_frequencies: IdMap<usize> = vec![1, 2, 2, 2, 2];
-//
-//         // matching blank top / bottom
-//         allow_all(BLANK_UP, BLANK_DOWN, Up, &mut adjacency_rules);
-//
-//         // connecting arm top / bottom
-//         allow_all(BLANK_DOWN, BLANK_UP, Up, &mut adjacency_rules);
-//
-//         // matching blank left / right
-//         allow_all(BLANK_RIGHT, BLANK_LEFT, Right, &mut adjacency_rules);
-//
-//         // connecting arm left / right
-//         allow_all(BLANK_LEFT, BLANK_RIGHT, Right, &mut adjacency_rules);
-//
-//         allow_all(B2, BLANK_LEFT, Right, &mut adjacency_rules);
-//         allow_all(B2, BLANK_RIGHT, Left, &mut adjacency_rules);
-//         allow_all(B2, BLANK_UP, Down, &mut adjacency_rules);
-//         allow_all(B2, BLANK_DOWN, Up, &mut adjacency_rules);
-//         for &dir in CardinalDirs::iter() {
-//             adjacency_rules.allow(BLANK, BLANK, dir);
-//         }
-//
-//         return Wfc::new_from_pattern_paths(paths, adjacency_rules, tile_frequencies)
-//             .with_tile_size(4);
-//     }
-// }
+#[cfg(test)]
+pub mod simple_patterns {
+    use super::*;
+    use adjacency_rules::{AdjacencyRules, CardinalDirs};
+    use tile::{IdMap, Weight};
+    use CardinalDirs::*;
+    pub const CHARS: [&str; 5] = ["' '", "┓", "┛", "┏", "┗"];
+
+    fn allow_all(
+        aaa: [usize; 2],
+        bbb: [usize; 2],
+        dir: CardinalDirs,
+        adjacency_rules: &mut AdjacencyRules,
+    ) {
+        for a in aaa {
+            for b in bbb {
+                adjacency_rules.allow(a, b, dir);
+            }
+        }
+    }
+
+    pub const BLANK: usize = 0; //' '
+    pub const DL: usize = 1; // ┓
+    pub const LU: usize = 2; // ┛
+    pub const RD: usize = 3; // ┏
+    pub const UR: usize = 4; // ┗
+
+    // ┓ ┛
+    pub const BLANK_RIGHT: [usize; 2] = [DL, LU];
+    // ┏ ┗
+    pub const BLANK_LEFT: [usize; 2] = [RD, UR];
+    // ┏ ┓
+    pub const BLANK_UP: [usize; 2] = [RD, DL];
+    // ┗ ┛
+    pub const BLANK_DOWN: [usize; 2] = [UR, LU];
+
+    pub const B2: [usize; 2] = [BLANK, BLANK];
+
+    pub const TILE_SIZE: u32 = 4;
+
+    fn load_pattern(name: &str) -> preprocessor::Pattern {
+        let path = format!("{}/../inputs/simple/{}.png", env!("CARGO_MANIFEST_DIR"), name);
+        return image::open(path)
+            .expect("simple pattern exists")
+            .to_rgba8()
+            .pixels()
+            .map(|p| p.0)
+            .collect();
+    }
+
+    pub fn construct_simple_patterns() -> WfcData {
+        let mut adjacency_rules = AdjacencyRules::new();
+        let patterns: IdMap<preprocessor::Pattern> = ["blank", "dl", "lu", "rd", "ur"]
+            .iter()
+            .map(|&name| load_pattern(name))
+            .collect();
+        let tile_frequencies: IdMap<Weight> = vec![1.0, 2.0, 2.0, 2.0, 2.0];
+
+        // matching blank top / bottom
+        allow_all(BLANK_UP, BLANK_DOWN, Up, &mut adjacency_rules);
+
+        // connecting arm top / bottom
+        allow_all(BLANK_DOWN, BLANK_UP, Up, &mut adjacency_rules);
+
+        // matching blank left / right
+        allow_all(BLANK_RIGHT, BLANK_LEFT, Right, &mut adjacency_rules);
+
+        // connecting arm left / right
+        allow_all(BLANK_LEFT, BLANK_RIGHT, Right, &mut adjacency_rules);
+
+        allow_all(B2, BLANK_LEFT, Right, &mut adjacency_rules);
+        allow_all(B2, BLANK_RIGHT, Left, &mut adjacency_rules);
+        allow_all(B2, BLANK_UP, Down, &mut adjacency_rules);
+        allow_all(B2, BLANK_DOWN, Up, &mut adjacency_rules);
+        for &dir in CardinalDirs::iter() {
+            adjacency_rules.allow(BLANK, BLANK, dir);
+        }
+
+        return WfcData {
+            tile_frequencies,
+            adjacency_rules,
+            patterns,
+            tile_size: UVec2::splat(TILE_SIZE),
+            cell_size: UVec2::splat(TILE_SIZE),
+        };
+    }
+
+    /// Asserts that every tile `tile_at` returns for `locs` is allowed next to the tiles
+    /// to its right and below it, skipping the cells without a tile
+    pub fn assert_follows_rules(
+        rules: &AdjacencyRules,
+        locs: impl IntoIterator<Item = glam::IVec2>,
+        tile_at: impl Fn(glam::IVec2) -> Option<usize>,
+    ) {
+        for loc in locs {
+            let Some(tile) = tile_at(loc) else {
+                continue;
+            };
+            for side in [Right, Down] {
+                let Some(neighbor) = tile_at(side + loc) else {
+                    continue;
+                };
+                assert!(
+                    rules.is_allowed(tile, neighbor, side),
+                    "{tile} next to {neighbor} at {loc} {side:?}"
+                );
+            }
+        }
+    }
+}
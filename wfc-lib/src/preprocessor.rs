@@ -1,5 +1,5 @@
-use glam::UVec2;
-use image::{GenericImageView, ImageBuffer, Rgba, RgbaImage, SubImage};
+use glam::{IVec2, UVec2};
+use image::{Rgba, RgbaImage};
 use std::{
     collections::{HashMap, HashSet, hash_map::DefaultHasher},
     vec::Vec,
@@ -10,7 +10,8 @@ use crate::{
         AdjacencyRules,
         CardinalDirs::{self, Down, Left, Right, Up},
     },
-    tile::{IdMap, TileId},
+    tile::{IdMap, TileId, Weight},
+    utils::UVecVec,
 };
 
 /// The actual pixel data of the tile_size x tile_size rectangle (PatternRect)
@@ -19,16 +20,49 @@ pub type RgbaPattern = Vec<Rgba<u8>>;
 pub type Pattern = Vec<[u8; 4]>;
 pub type PatternRef<'p> = Vec<&'p [u8; 4]>;
 pub type U8Pattern = Vec<u8>;
-type PatternSubImage<'a> = SubImage<&'a ImageBuffer<Rgba<u8>, Vec<u8>>>;
 type LocIdHMap = HashMap<UVec2, usize>;
-type Edge = RgbaPattern;
+type Edge = Pattern;
 // type IdPatternHMap = HashMap<usize, Pattern>;
 
 /// The data returned by a preprocessor required to run the wfc algorithm
 pub struct WfcData {
-    pub tile_frequencies: IdMap<usize>,
+    pub tile_frequencies: IdMap<Weight>,
     pub adjacency_rules: AdjacencyRules,
     pub patterns: IdMap<Pattern>,
+    /// The size of each pattern in pixels
+    pub tile_size: UVec2,
+    /// The size in pixels of the region of the output a cell covers. The whole
+    /// pattern for the tiled method and a single pixel for the overlapping method
+    pub cell_size: UVec2,
+}
+
+impl WfcData {
+    /// The id of each pattern. Patterns that appear more than once map to their first id
+    pub fn pattern_ids(&self) -> HashMap<&Pattern, TileId> {
+        let mut pattern_ids = HashMap::with_capacity(self.patterns.len());
+        for (id, pattern) in self.patterns.iter().enumerate() {
+            pattern_ids.entry(pattern).or_insert(id);
+        }
+        return pattern_ids;
+    }
+
+    /// The part of each pattern that is drawn for a cell collapsed to it, i.e. the
+    /// top left `cell_size` pixels of the pattern
+    pub fn cell_patterns(&self) -> IdMap<Pattern> {
+        if self.cell_size == self.tile_size {
+            return self.patterns.clone();
+        }
+        return self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                get_tile_locs(self.cell_size, UVec2::ONE)
+                    .into_iter()
+                    .map(|l| pattern[(l.y * self.tile_size.x + l.x) as usize])
+                    .collect()
+            })
+            .collect();
+    }
 }
 
 use std::fmt::Debug;
@@ -38,6 +72,8 @@ impl Debug for WfcData {
             .field("tile_frequencies", &self.tile_frequencies)
             .field("adjacency_rules", &self.adjacency_rules)
             .field("patterns (len)", &self.patterns.len())
+            .field("tile_size", &self.tile_size)
+            .field("cell_size", &self.cell_size)
             .finish()
     }
 }
@@ -79,6 +115,25 @@ pub enum PatternMethod {
     Tiled,
 }
 
+/// Which rotations and reflections of the patterns found in the input are also used
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "web",
+    derive(serde::Deserialize, tsify::Tsify),
+    serde(rename_all = "lowercase")
+)]
+pub enum Symmetry {
+    /// Only the patterns as they appear in the input
+    #[default]
+    None,
+    /// The 90, 180 and 270 degree rotations of each pattern
+    Rotation,
+    /// The horizontal and vertical reflections of each pattern
+    Reflection,
+    /// Every rotation and reflection of each pattern
+    All,
+}
+
 #[cfg(feature = "web")]
 #[derive(serde::Deserialize, tsify::Tsify, Copy, Clone, Debug)]
 #[serde(rename = "UVec2", remote = "UVec2")]
@@ -105,6 +160,14 @@ pub struct Config {
     pub tile_size: UVec2,
     pub adjacency_method: AdjacencyMethod,
     pub pattern_method: PatternMethod,
+    /// Treat the input image as periodic, i.e. tiles on the right (bottom) edge are
+    /// considered adjacent to the tiles on the left (top) edge. The edge based adjacency
+    /// methods match the edges of every pair of patterns so they aren't affected
+    #[cfg_attr(feature = "web", serde(default))]
+    pub wrap: bool,
+    /// Augment the patterns with their rotations and/or reflections
+    #[cfg_attr(feature = "web", serde(default))]
+    pub symmetry: Symmetry,
 }
 
 fn preprocess_simple_tiled(image: RgbaImage, config: Config) -> WfcData {
@@ -136,23 +199,6 @@ fn preprocess_simple_tiled(image: RgbaImage, config: Config) -> WfcData {
         };
         loc_id_map[loc] = Some(pattern_id);
 
-        // construct adjacency rules
-        // if self.config.wrap {
-        //     let max = image_dims - (image_dims % tile_size as u32) - UVec2::ONE;
-        //     let on_right_edge = loc.x == max.x;
-        //     let on_top_edge = loc.y == max.y;
-        //     if on_right_edge {
-        //         let left_loc = UVec2 { x: 0, y: loc.y };
-        //         let left_id = self.loc_id_map[&left_loc];
-        //         adjacency_rules.allow(pattern_id, left_id, Right);
-        //     }
-        //     if on_top_edge {
-        //         let bottom_loc = loc - UVec2 { x: loc.x, y: 0 };
-        //         let bottom_id = self.loc_id_map[&bottom_loc];
-        //         adjacency_rules.allow(pattern_id, bottom_id, Down);
-        //     }
-        // }
-
         // patterns are processed in column major order so left (<) and below (v) tiles are already extracted
         // add the adjacency rules in these directions if not on an edge
         let on_left_edge = loc.x == 0;
@@ -169,11 +215,29 @@ fn preprocess_simple_tiled(image: RgbaImage, config: Config) -> WfcData {
         }
     }
 
+    if config.wrap {
+        allow_wrapped(&mut adjacency_rules, image_dims, tile_size, |loc| {
+            loc_id_map[loc].expect("all tiles processed")
+        });
+    }
+
     let patterns = patterns.into_iter().map(pattern_ref_to_owned).collect();
+    let augmented = Augmented::new(patterns, &tile_frequencies, tile_size, config.symmetry);
+    let observed_rules = adjacency_rules;
+    let mut adjacency_rules = AdjacencyRules::new();
+    augmented.allow_transformed(&observed_rules, &mut adjacency_rules);
+
+    let Augmented {
+        patterns,
+        tile_frequencies,
+        ..
+    } = augmented;
     return WfcData {
         tile_frequencies,
         adjacency_rules,
         patterns,
+        tile_size,
+        cell_size: tile_size,
     };
 }
 
@@ -181,58 +245,34 @@ fn preprocess_edges(image: RgbaImage, config: Config) -> WfcData {
     let tile_size = config.tile_size;
     let mut vsides: HashSet<Edge> = HashSet::new();
     let mut hsides: HashSet<Edge> = HashSet::new();
-    let mut edgemap: IdMap<[Edge; 4]> = IdMap::new();
 
     let hdirs = [Up, Down];
     let vdirs = [Left, Right];
 
-    let mut patterns: IdMap<PatternRef> = IdMap::new();
-    let mut tile_frequencies = IdMap::new();
-
-    let mut current_id = 0;
-
-
-    for loc in get_tile_locs(image.dimensions().into(), tile_size) {
-        let pattern: PatternRef = pattern_at(&image, loc, tile_size);
-        // TODO: rework edge functions to use patterns instead of sub-images
-        let sub_img = sub_image_at(&image, loc, tile_size);
-
-        let id = if let Some(existing_id) = patterns.iter().position(|p| p == &pattern) {
-            tile_frequencies[existing_id] += 1;
-            existing_id
-        } else {
-            // add new pattern
-            let new_pattern_id = current_id;
-            current_id += 1;
-            patterns.push(pattern);
-            tile_frequencies.push(1);
-            new_pattern_id
-        };
-
-        let mut edges = get_edges(&sub_img, tile_size);
-
-        if let AdjacencyMethod::Edge(EdgeMethod::Flip) = config.adjacency_method {
-            edges[Down].reverse();
-            edges[Right].reverse();
-        }
+    let (patterns, tile_frequencies, _) = extract_unique_patterns(&image, tile_size);
+    let Augmented {
+        patterns,
+        tile_frequencies,
+        ..
+    } = Augmented::new(patterns, &tile_frequencies, tile_size, config.symmetry);
 
-        for vdir in vdirs {
-            vsides.insert(edges[vdir].clone());
-        }
-        for hdir in hdirs {
-            hsides.insert(edges[hdir].clone());
-        }
-        // old id
-        if id < edgemap.len() {
-            // do nothing
-        }
-        // new id
-        else if id == edgemap.len() {
-            edgemap.push(edges);
-        } else {
-            unreachable!("id was incremented twice");
-        }
-    }
+    let edgemap: IdMap<[Edge; 4]> = patterns
+        .iter()
+        .map(|pattern| {
+            let mut edges = pattern_edges(pattern, tile_size);
+            if let AdjacencyMethod::Edge(EdgeMethod::Flip) = config.adjacency_method {
+                edges[Down].reverse();
+                edges[Right].reverse();
+            }
+            for vdir in vdirs {
+                vsides.insert(edges[vdir].clone());
+            }
+            for hdir in hdirs {
+                hsides.insert(edges[hdir].clone());
+            }
+            return edges;
+        })
+        .collect();
     type EdgeId = usize;
     let vside_map: HashMap<&Edge, EdgeId> = vsides
         .iter()
@@ -280,106 +320,40 @@ fn preprocess_edges(image: RgbaImage, config: Config) -> WfcData {
         }
     }
 
-    let patterns: IdMap<Pattern> = patterns.into_iter().map(pattern_ref_to_owned).collect();
-
     WfcData {
         tile_frequencies,
         adjacency_rules,
         patterns,
+        tile_size,
+        cell_size: tile_size,
     }
 }
 
 fn preprocess_adjacent_edges(image: RgbaImage, config: Config) -> WfcData {
     let tile_size = config.tile_size;
     // the unique patterns in the image
-    let mut patterns: IdMap<Pattern> = IdMap::new();
+    let (patterns, tile_frequencies, _) = extract_unique_patterns(&image, tile_size);
+    let augmented = Augmented::new(patterns, &tile_frequencies, tile_size, config.symmetry);
+    let patterns = &augmented.patterns;
+
     // map of edge id to the patterns it was found in organized by the side it was found on
     let mut edgemap: IdMap<[HashSet<usize>; 4]> = IdMap::new();
     let mut edge_ids: HashMap<Edge, usize> = HashMap::new();
-    let mut tile_frequencies: IdMap<TileId> = IdMap::new();
-
-    let mut current_id = 0;
-    let mut current_edge_id = 0;
-    let image_dims: UVec2 = image.dimensions().into();
     // ids of each pattern's edges
     let mut pattern_edge_ids: IdMap<[usize; 4]> = IdMap::new();
-    let mut edge_adjacencies = AdjacencyRules::new();
-    let mut loc_id_map = crate::utils::UVecVec(vec![
-        vec![None; image_dims.x as usize];
-        image_dims.y as usize
-    ]);
-
-    let locs = get_tile_locs(image_dims, tile_size);
-    for loc in locs {
-        let pattern_img = sub_image_at(&image, loc, tile_size);
-        let pattern: Pattern = pattern_img.pixels().map(|(_, _, rgba)| rgba.0).collect();
-        
-        let pattern_id = if let Some(existing_id) = patterns.iter().position(|p| p == &pattern) {
-            tile_frequencies[existing_id] += 1;
-            existing_id
-        } else {
-            // add new pattern
-            let id = current_id;
-            current_id += 1;
-            patterns.push(pattern);
-            tile_frequencies.push(1);
-            id
-        };
 
-        // mut and Option are used to avoid cloning the edges
-        let mut edges = get_edges(&pattern_img, tile_size).map(|e| Some(e));
-        pattern_edge_ids.push(Default::default());
-        for side in 0..4 {
-            let edge_id = match edge_ids.get(edges[side].as_ref().unwrap()) {
-                Some(&edge_id) => {
-                    edgemap[edge_id][side].insert(pattern_id);
-                    edge_id
-                }
-                None => {
-                    let edge_id = current_edge_id;
-                    current_edge_id += 1;
-                    edge_ids.insert(edges[side].take().unwrap(), edge_id);
-                    let mut edge_patterns: [HashSet<usize>; 4] = Default::default();
-                    edge_patterns[side].insert(pattern_id);
-                    edgemap.push(edge_patterns);
-                    edge_id
-                }
-            };
-            pattern_edge_ids[pattern_id][side] = edge_id;
-        }
-        loc_id_map[loc] = Some(pattern_edge_ids[pattern_id]);
-
-        // construct adjacency rules
-        // if self.config.wrap {
-        //     let max = image_dims - (image_dims % tile_size as u32) - UVec2::ONE;
-        //     let on_right_edge = loc.x == max.x;
-        //     let on_top_edge = loc.y == max.y;
-        //     if on_right_edge {
-        //         let left_loc = UVec2 { x: 0, y: loc.y };
-        //         let left_id = self.loc_id_map[&left_loc];
-        //         adjacency_rules.allow(pattern_id, left_id, Right);
-        //     }
-        //     if on_top_edge {
-        //         let bottom_loc = loc - UVec2 { x: loc.x, y: 0 };
-        //         let bottom_id = self.loc_id_map[&bottom_loc];
-        //         adjacency_rules.allow(pattern_id, bottom_id, Down);
-        //     }
-        // }
-
-        // patterns are processed in column major order so left (<) and below (v) tiles are already extracted
-        // add the adjacency rules in these directions if not on an edge
-        let on_left_edge = loc.x == 0;
-        let on_bottom_edge = loc.y == 0;
-        if !on_bottom_edge {
-            let bottom_loc = loc - UVec2 { x: 0, y: tile_size.y };
-            let bottom_id = loc_id_map[bottom_loc].expect("tile below already processed")[Up];
-            edge_adjacencies.allow(pattern_id, bottom_id, Down);
-        }
-        if !on_left_edge {
-            let left_loc = loc - UVec2 { x: tile_size.x, y: 0 };
-            let left_id = loc_id_map[left_loc].expect("tile left already processed")[Right];
-            edge_adjacencies.allow(pattern_id, left_id, Left);
+    for (pattern_id, pattern) in patterns.iter().enumerate() {
+        let edges = pattern_edges(pattern, tile_size);
+        let mut ids: [usize; 4] = Default::default();
+        for (side, edge) in edges.into_iter().enumerate() {
+            let edge_id = *edge_ids.entry(edge).or_insert_with(|| {
+                edgemap.push(Default::default());
+                return edgemap.len() - 1;
+            });
+            edgemap[edge_id][side].insert(pattern_id);
+            ids[side] = edge_id;
         }
+        pattern_edge_ids.push(ids);
     }
 
     let mut adjacency_rules: AdjacencyRules = AdjacencyRules::new();
@@ -391,17 +365,111 @@ fn preprocess_adjacent_edges(image: RgbaImage, config: Config) -> WfcData {
             }
         }
     }
+    // the edges of every pair of patterns are matched, so patterns that are neighbors
+    // across a wrapped border are already allowed exactly when their edges match
+    log::info!("found {} patterns", patterns.len());
+
+    let Augmented {
+        patterns,
+        tile_frequencies,
+        ..
+    } = augmented;
+    return WfcData {
+        tile_frequencies,
+        adjacency_rules,
+        patterns,
+        tile_size,
+        cell_size: tile_size,
+    };
+}
+
+/// Extracts every `tile_size` window of the image as a pattern. Patterns are adjacent
+/// when they agree on the pixels they share when overlapped with an offset of one
+/// pixel in that direction
+fn preprocess_overlapping(image: RgbaImage, config: Config) -> WfcData {
+    let tile_size = config.tile_size;
+    let image_dims: UVec2 = image.dimensions().into();
+    let mut patterns: IdMap<Pattern> = IdMap::new();
+    let mut pattern_ids: HashMap<Pattern, TileId> = HashMap::new();
+    let mut tile_frequencies: IdMap<usize> = IdMap::new();
+
+    // without wrapping windows must fit completely inside the image
+    let window_locs = if config.wrap {
+        image_dims
+    } else {
+        let fits = image_dims + UVec2::ONE;
+        UVec2::new(
+            fits.x.saturating_sub(tile_size.x),
+            fits.y.saturating_sub(tile_size.y),
+        )
+    };
+    for loc in get_tile_locs(window_locs, UVec2::ONE) {
+        let pattern: Pattern = get_tile_locs(tile_size, UVec2::ONE)
+            .into_iter()
+            .map(|offset| (loc + offset) % image_dims)
+            .map(|l| image.get_pixel(l.x, l.y).0)
+            .collect();
+        match pattern_ids.get(&pattern) {
+            Some(&existing_id) => tile_frequencies[existing_id] += 1,
+            None => {
+                pattern_ids.insert(pattern.clone(), patterns.len());
+                patterns.push(pattern);
+                tile_frequencies.push(1);
+            }
+        }
+    }
+
+    let Augmented {
+        patterns,
+        tile_frequencies,
+        ..
+    } = Augmented::new(patterns, &tile_frequencies, tile_size, config.symmetry);
+
+    let mut adjacency_rules = AdjacencyRules::new();
+    for (id, pattern) in patterns.iter().enumerate() {
+        for (other_id, other_pattern) in patterns.iter().enumerate() {
+            // allow also adds the opposite direction
+            for dir in [Right, Down] {
+                if overlap_agrees(pattern, other_pattern, tile_size, dir) {
+                    adjacency_rules.allow(id, other_id, dir);
+                }
+            }
+        }
+    }
     log::info!("found {} patterns", patterns.len());
 
-    // let patterns = patterns.into_iter().map(pattern_ref_to_owned).collect();
     return WfcData {
         tile_frequencies,
         adjacency_rules,
         patterns,
+        tile_size,
+        cell_size: UVec2::ONE,
     };
 }
 
+/// Whether `other` placed one pixel in `dir` from `pattern` matches it on every pixel
+/// they overlap
+fn overlap_agrees(pattern: &Pattern, other: &Pattern, tile_size: UVec2, dir: CardinalDirs) -> bool {
+    let offset: IVec2 = dir.into();
+    let size = tile_size.as_ivec2();
+    for loc in get_tile_locs(tile_size, UVec2::ONE) {
+        let other_loc = loc.as_ivec2() - offset;
+        if other_loc.cmplt(IVec2::ZERO).any() || other_loc.cmpge(size).any() {
+            continue;
+        }
+        let i = (loc.y * tile_size.x + loc.x) as usize;
+        let other_i = (other_loc.y * size.x + other_loc.x) as usize;
+        if pattern[i] != other[other_i] {
+            return false;
+        }
+    }
+    return true;
+}
+
 pub fn preprocess(image: RgbaImage, config: Config) -> WfcData {
+    if let PatternMethod::Overlapping = config.pattern_method {
+        return preprocess_overlapping(image, config);
+    }
     match config.adjacency_method {
         AdjacencyMethod::Adjacency => {
             return preprocess_simple_tiled(image, config);
@@ -416,18 +484,166 @@ pub fn preprocess(image: RgbaImage, config: Config) -> WfcData {
     }
 }
 
-fn get_edges(sub_img: &PatternSubImage, tile_size: UVec2) -> [Edge; 4] {
-    // TODO: implement the logic done by sub_img.view for patterns (it's not that hard and will
-    // remove the need to extract pattern twice)
+/// Allows the tiles on the right (bottom) edge of the image to the left of (above) the
+/// tiles on the left (top) edge in the same row (column)
+fn allow_wrapped(
+    adjacency_rules: &mut AdjacencyRules,
+    image_dims: UVec2,
+    tile_size: UVec2,
+    pattern_id_at: impl Fn(UVec2) -> TileId,
+) {
+    // trim edges
+    let max = image_dims - (image_dims % tile_size);
+    if max.x == 0 || max.y == 0 {
+        return;
+    }
+    let last = max - tile_size;
+    for y in (0..max.y).step_by(tile_size.y as usize) {
+        let right_id = pattern_id_at(UVec2 { x: last.x, y });
+        let left_id = pattern_id_at(UVec2 { x: 0, y });
+        adjacency_rules.allow(right_id, left_id, Right);
+    }
+    for x in (0..max.x).step_by(tile_size.x as usize) {
+        let bottom_id = pattern_id_at(UVec2 { x, y: last.y });
+        let top_id = pattern_id_at(UVec2 { x, y: 0 });
+        adjacency_rules.allow(bottom_id, top_id, Down);
+    }
+}
+
+impl Symmetry {
+    /// The transforms applied to each pattern. Quarter turns of non square tiles are
+    /// skipped as they would change the size of the tile
+    fn transforms(self, tile_size: UVec2) -> Vec<Transform> {
+        let flips: &[bool] = match self {
+            Symmetry::None | Symmetry::Rotation => &[false],
+            Symmetry::Reflection | Symmetry::All => &[false, true],
+        };
+        let turns: &[u8] = match self {
+            Symmetry::None => &[0],
+            // reflecting horizontally and vertically is the same as a half turn
+            Symmetry::Reflection => &[0, 2],
+            Symmetry::Rotation | Symmetry::All if tile_size.x == tile_size.y => &[0, 1, 2, 3],
+            Symmetry::Rotation | Symmetry::All => {
+                log::warn!("tile size {tile_size} is not square, only using half turns");
+                &[0, 2]
+            }
+        };
+        return flips
+            .iter()
+            .flat_map(|&flip| turns.iter().map(move |&turns| Transform { flip, turns }))
+            .collect();
+    }
+}
+
+/// A horizontal flip followed by a number of clockwise quarter turns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Transform {
+    flip: bool,
+    turns: u8,
+}
+
+impl Transform {
+    fn apply_offset(self, offset: IVec2) -> IVec2 {
+        let mut offset = offset;
+        if self.flip {
+            offset.x = -offset.x;
+        }
+        for _ in 0..self.turns {
+            offset = IVec2::new(-offset.y, offset.x);
+        }
+        return offset;
+    }
+
+    fn apply_dir(self, dir: CardinalDirs) -> CardinalDirs {
+        let offset = self.apply_offset(dir.into());
+        return CardinalDirs::as_array()
+            .into_iter()
+            .find(|&d| IVec2::from(d) == offset)
+            .expect("transformed direction is cardinal");
+    }
+
+    fn apply_pattern(self, pattern: &Pattern, tile_size: UVec2) -> Pattern {
+        // locs are doubled so the center of the pattern is on the grid
+        let max = tile_size.as_ivec2() - IVec2::ONE;
+        let mut transformed = pattern.clone();
+        for (i, loc) in get_tile_locs(tile_size, UVec2::ONE).into_iter().enumerate() {
+            let centered = loc.as_ivec2() * 2 - max;
+            let loc = (self.apply_offset(centered) + max) / 2;
+            transformed[(loc.y * tile_size.x as i32 + loc.x) as usize] = pattern[i];
+        }
+        return transformed;
+    }
+}
+
+/// Patterns extended with the transforms of a [`Symmetry`]. The original patterns keep
+/// their ids and the frequencies of patterns that transform into each other are summed
+struct Augmented {
+    patterns: IdMap<Pattern>,
+    tile_frequencies: IdMap<Weight>,
+    /// Each transform with the id every original pattern is transformed into
+    transformed_ids: Vec<(Transform, IdMap<TileId>)>,
+}
+
+impl Augmented {
+    fn new(
+        patterns: IdMap<Pattern>,
+        tile_frequencies: &IdMap<usize>,
+        tile_size: UVec2,
+        symmetry: Symmetry,
+    ) -> Self {
+        let mut pattern_ids: HashMap<Pattern, TileId> = patterns
+            .iter()
+            .enumerate()
+            .map(|(id, pattern)| (pattern.clone(), id))
+            .collect();
+        let num_original = patterns.len();
+        let mut augmented = Self {
+            tile_frequencies: vec![0.0; num_original],
+            patterns,
+            transformed_ids: Vec::new(),
+        };
+        for transform in symmetry.transforms(tile_size) {
+            let mut ids = IdMap::with_capacity(num_original);
+            for (id, &frequency) in tile_frequencies.iter().enumerate() {
+                let pattern = transform.apply_pattern(&augmented.patterns[id], tile_size);
+                let transformed_id = *pattern_ids.entry(pattern).or_insert_with_key(|pattern| {
+                    augmented.patterns.push(pattern.clone());
+                    augmented.tile_frequencies.push(0.0);
+                    return augmented.patterns.len() - 1;
+                });
+                augmented.tile_frequencies[transformed_id] += frequency as Weight;
+                ids.push(transformed_id);
+            }
+            augmented.transformed_ids.push((transform, ids));
+        }
+        return augmented;
+    }
+
+    /// Allows every transform of the rules observed between the original patterns
+    fn allow_transformed(&self, observed: &AdjacencyRules, rules: &mut AdjacencyRules) {
+        for (from, to, dir) in observed.iter() {
+            for (transform, ids) in self.transformed_ids.iter() {
+                rules.allow(ids[from], ids[to], transform.apply_dir(dir));
+            }
+        }
+    }
+}
+
+/// The pixels along each side of the pattern ordered left to right (top to bottom)
+fn pattern_edges(pattern: &Pattern, tile_size: UVec2) -> [Edge; 4] {
+    let max = tile_size - UVec2::ONE;
     return CardinalDirs::as_array().map(|dir| {
-        let sub_sub_img = match dir {
-            Up => sub_img.view(0, 0, tile_size.x, 1),
-            Left => sub_img.view(0, 0, 1, tile_size.y),
-            Right => sub_img.view(tile_size.x - 1, 0, 1, tile_size.y),
-            Down => sub_img.view(0, tile_size.y - 1, tile_size.x, 1),
+        let (origin, size) = match dir {
+            Up => (UVec2::ZERO, UVec2::new(tile_size.x, 1)),
+            Left => (UVec2::ZERO, UVec2::new(1, tile_size.y)),
+            Right => (UVec2::new(max.x, 0), UVec2::new(1, tile_size.y)),
+            Down => (UVec2::new(0, max.y), UVec2::new(tile_size.x, 1)),
         };
-        let edge: Vec<Rgba<u8>> = sub_sub_img.pixels().map(|(_, _, rgba)| rgba).collect();
-        return edge;
+        return get_tile_locs(size, UVec2::ONE)
+            .into_iter()
+            .map(|l| l + origin)
+            .map(|l| pattern[(l.y * tile_size.x + l.x) as usize])
+            .collect();
     });
 }
 
@@ -449,16 +665,8 @@ pub fn get_tile_locs(image_dims: UVec2, tile_size: UVec2) -> Vec<UVec2> {
     return locs;
 }
 
-fn sub_image_at(
-    image: &RgbaImage,
-    loc: UVec2,
-    tile_size: UVec2,
-) -> SubImage<&ImageBuffer<Rgba<u8>, Vec<u8>>> {
-    return image.view(loc.x, loc.y, tile_size.x, tile_size.y);
-}
-
 pub fn pattern_at(image: &RgbaImage, loc: UVec2, tile_size: UVec2) -> PatternRef {
-    // this is what sub_image_at under the hood
+    // this is what a sub image view does under the hood
     get_tile_locs(tile_size, UVec2::ONE)
         .into_iter()
         .map(|l| l + loc)
@@ -478,6 +686,40 @@ fn extract_patterns(image: &RgbaImage, tile_size: UVec2) -> Vec<(UVec2, PatternR
         .collect();
 }
 
+/// The unique patterns of the tiles in the image, their frequencies and the id of the
+/// pattern at each tile loc
+fn extract_unique_patterns(
+    image: &RgbaImage,
+    tile_size: UVec2,
+) -> (IdMap<Pattern>, IdMap<usize>, UVecVec<Option<TileId>>) {
+    let image_dims: UVec2 = image.dimensions().into();
+    let mut patterns: IdMap<Pattern> = IdMap::new();
+    let mut pattern_ids: HashMap<Pattern, TileId> = HashMap::new();
+    let mut tile_frequencies: IdMap<usize> = IdMap::new();
+    let mut loc_id_map = UVecVec(vec![
+        vec![None; image_dims.x as usize];
+        image_dims.y as usize
+    ]);
+
+    for (loc, pattern) in extract_patterns(image, tile_size) {
+        let pattern = pattern_ref_to_owned(pattern);
+        let id = match pattern_ids.get(&pattern) {
+            Some(&existing_id) => {
+                tile_frequencies[existing_id] += 1;
+                existing_id
+            }
+            None => {
+                pattern_ids.insert(pattern.clone(), patterns.len());
+                patterns.push(pattern);
+                tile_frequencies.push(1);
+                patterns.len() - 1
+            }
+        };
+        loc_id_map[loc] = Some(id);
+    }
+    return (patterns, tile_frequencies, loc_id_map);
+}
+
 fn pattern_ref_to_owned(pref: PatternRef) -> Pattern {
     pref.into_iter().map(|p| p.to_owned()).collect()
 }
@@ -485,4 +727,190 @@ fn pattern_ref_to_owned(pref: PatternRef) -> Pattern {
 #[cfg(test)]
 mod test {
     // TODO: recreate tests
+    use super::*;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const GREEN: Rgba<u8> = Rgba([0, 255, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    /// a single row of 1x1 tiles: red green blue
+    fn rgb_row() -> RgbaImage {
+        let colors = [RED, GREEN, BLUE];
+        return RgbaImage::from_fn(3, 1, |x, _| colors[x as usize]);
+    }
+
+    fn overlapping_config(tile_size: u32, wrap: bool) -> Config {
+        return Config {
+            tile_size: UVec2::splat(tile_size),
+            adjacency_method: AdjacencyMethod::Adjacency,
+            pattern_method: PatternMethod::Overlapping,
+            wrap,
+            symmetry: Symmetry::None,
+        };
+    }
+
+    fn checkerboard(size: u32) -> RgbaImage {
+        return RgbaImage::from_fn(size, size, |x, y| if (x + y) % 2 == 0 { RED } else { BLUE });
+    }
+
+    fn tiled_config(wrap: bool) -> Config {
+        return Config {
+            tile_size: UVec2::ONE,
+            adjacency_method: AdjacencyMethod::Adjacency,
+            pattern_method: PatternMethod::Tiled,
+            wrap,
+            symmetry: Symmetry::None,
+        };
+    }
+
+    #[test]
+    fn no_rules_across_border_without_wrap() {
+        let data = preprocess(rgb_row(), tiled_config(false));
+        let [red, green, blue] = [0, 1, 2];
+        assert!(data.adjacency_rules.is_allowed(red, green, Right));
+        assert!(!data.adjacency_rules.is_allowed(blue, red, Right));
+        assert!(!data.adjacency_rules.is_allowed(red, red, Down));
+    }
+
+    #[test]
+    fn wrap_learns_rules_across_border() {
+        let data = preprocess(rgb_row(), tiled_config(true));
+        let [red, green, blue] = [0, 1, 2];
+        assert!(data.adjacency_rules.is_allowed(blue, red, Right));
+        assert!(data.adjacency_rules.is_allowed(red, blue, Left));
+        // the single row wraps onto itself
+        for id in [red, green, blue] {
+            assert!(data.adjacency_rules.is_allowed(id, id, Down));
+        }
+    }
+
+    #[test]
+    fn wrap_keeps_adjacent_edges_matching() {
+        // the left and right columns don't share an edge
+        let image = RgbaImage::from_fn(4, 2, |x, _| [RED, GREEN, GREEN, BLUE][x as usize]);
+        let config = |wrap| Config {
+            tile_size: UVec2::splat(2),
+            adjacency_method: AdjacencyMethod::Edge(EdgeMethod::Adjacent),
+            pattern_method: PatternMethod::Tiled,
+            wrap,
+            symmetry: Symmetry::None,
+        };
+        let rules = |data: WfcData| {
+            let mut rules: Vec<_> = data.adjacency_rules.iter().collect();
+            rules.sort_by_key(|&(from, to, dir)| (from, to, usize::from(dir)));
+            return rules;
+        };
+        let wrapped = preprocess(image.clone(), config(true));
+        let [red_green, green_blue] = [0, 1];
+        assert!(!wrapped
+            .adjacency_rules
+            .is_allowed(green_blue, red_green, Right));
+        assert_eq!(rules(wrapped), rules(preprocess(image, config(false))));
+    }
+
+    #[test]
+    fn overlapping_extracts_every_window() {
+        let data = preprocess(checkerboard(4), overlapping_config(2, false));
+        // the two phases of the checkerboard
+        assert_eq!(data.patterns.len(), 2);
+        // 3x3 windows fit in a 4x4 image
+        assert_eq!(data.tile_frequencies.iter().sum::<Weight>(), 9.0);
+        assert_eq!(data.cell_size, UVec2::ONE);
+
+        let wrapped = preprocess(checkerboard(4), overlapping_config(2, true));
+        assert_eq!(wrapped.tile_frequencies.iter().sum::<Weight>(), 16.0);
+    }
+
+    #[test]
+    fn overlapping_patterns_adjacent_when_overlap_agrees() {
+        let data = preprocess(checkerboard(4), overlapping_config(2, false));
+        let rules = &data.adjacency_rules;
+        for dir in CardinalDirs::iter().copied() {
+            assert!(rules.is_allowed(0, 1, dir));
+            assert!(rules.is_allowed(1, 0, dir));
+            assert!(!rules.is_allowed(0, 0, dir));
+            assert!(!rules.is_allowed(1, 1, dir));
+        }
+    }
+
+    #[test]
+    fn transforms_rotate_clockwise_after_flipping() {
+        let [a, b, c, d] = [RED.0, GREEN.0, BLUE.0, [0; 4]];
+        let pattern = vec![a, b, c, d];
+        let size = UVec2::splat(2);
+        let quarter_turn = Transform {
+            flip: false,
+            turns: 1,
+        };
+        assert_eq!(quarter_turn.apply_pattern(&pattern, size), vec![c, a, d, b]);
+        assert_eq!(quarter_turn.apply_dir(Right), Down);
+        assert_eq!(quarter_turn.apply_dir(Up), Right);
+        let flip = Transform {
+            flip: true,
+            turns: 0,
+        };
+        assert_eq!(flip.apply_pattern(&pattern, size), vec![b, a, d, c]);
+        assert_eq!(flip.apply_dir(Right), Left);
+        assert_eq!(flip.apply_dir(Up), Up);
+    }
+
+    #[test]
+    fn symmetry_transforms_learned_rules() {
+        let config = Config {
+            symmetry: Symmetry::Rotation,
+            ..tiled_config(false)
+        };
+        let data = preprocess(rgb_row(), config);
+        let [red, green, blue] = [0, 1, 2];
+        // 1x1 patterns are their own rotations
+        assert_eq!(data.patterns.len(), 3);
+        assert_eq!(data.tile_frequencies, vec![4.0, 4.0, 4.0]);
+        assert!(data.adjacency_rules.allowed_in_all_dirs(red, green));
+        assert!(data.adjacency_rules.allowed_in_all_dirs(green, blue));
+        assert!(!data.adjacency_rules.is_allowed(red, blue, Right));
+    }
+
+    #[test]
+    fn symmetry_edges_match_across_transforms() {
+        // red on top, green on the bottom
+        let image = RgbaImage::from_fn(2, 2, |_, y| if y == 0 { RED } else { GREEN });
+        let config = Config {
+            tile_size: UVec2::splat(2),
+            adjacency_method: AdjacencyMethod::Edge(EdgeMethod::Perfect),
+            pattern_method: PatternMethod::Tiled,
+            wrap: false,
+            symmetry: Symmetry::Rotation,
+        };
+        let data = preprocess(image.clone(), config);
+        assert_eq!(data.patterns.len(), 4);
+        assert_eq!(data.tile_frequencies, vec![1.0; 4]);
+        let red_bottom = data
+            .patterns
+            .iter()
+            .position(|p| p == &vec![GREEN.0, GREEN.0, RED.0, RED.0])
+            .unwrap();
+        // the red edges of the original and its half turn line up
+        assert!(data.adjacency_rules.is_allowed(0, red_bottom, Up));
+        assert!(!data.adjacency_rules.is_allowed(0, 0, Up));
+
+        let reflected = preprocess(
+            image,
+            Config {
+                symmetry: Symmetry::Reflection,
+                ..config
+            },
+        );
+        // the vertical reflection is the half turn and the horizontal one is the original
+        assert_eq!(reflected.patterns.len(), 2);
+        assert_eq!(reflected.tile_frequencies, vec![2.0, 2.0]);
+    }
+
+    #[test]
+    fn cell_patterns_are_top_left_pixel() {
+        let data = preprocess(checkerboard(4), overlapping_config(2, false));
+        let cell_patterns = data.cell_patterns();
+        for (pattern, cell_pattern) in data.patterns.iter().zip(cell_patterns) {
+            assert_eq!(cell_pattern, vec![pattern[0]]);
+        }
+    }
 }
@@ -6,6 +6,9 @@ pub type TileId = usize;
 /// A list of type T indexable by TileId
 pub type IdMap<T> = Vec<T>;
 
+/// The relative weight of a tile, i.e. how likely it is to be picked. Must be finite and not negative
+pub type Weight = f64;
+
 /// A tile is a wrapper around a pattern from the source image
 /// with additional info
 pub struct Tile {
@@ -13,3 +16,122 @@ pub struct Tile {
     pub pattern: RgbaPattern,
     pub frequency: u32,
 }
+
+pub(crate) type Word = u64;
+const WORD_BITS: usize = Word::BITS as usize;
+
+/// A set of tile ids stored as a fixed width bitset with one bit per tile
+///
+/// Borrows its words so the sets of every cell can be stored in one flat vec
+#[derive(Debug, Clone, Copy)]
+pub struct TileSet<'a> {
+    words: &'a [Word],
+}
+
+impl<'a> TileSet<'a> {
+    pub(crate) fn new(words: &'a [Word]) -> Self {
+        return Self { words };
+    }
+
+    /// The number of words needed to store a set of `num_tiles` tiles
+    pub(crate) fn words_for(num_tiles: usize) -> usize {
+        return num_tiles.div_ceil(WORD_BITS);
+    }
+
+    pub fn contains(&self, id: TileId) -> bool {
+        return self.words[id / WORD_BITS] & (1 << (id % WORD_BITS)) != 0;
+    }
+
+    pub fn len(&self) -> usize {
+        return self
+            .words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.words.iter().all(|&word| word == 0);
+    }
+
+    /// The tiles in the set in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = TileId> + 'a {
+        return self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            return std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                // clear the lowest set bit
+                word &= word - 1;
+                return Some(i * WORD_BITS + bit);
+            });
+        });
+    }
+}
+
+/// The mutable counterpart of [`TileSet`]
+#[derive(Debug)]
+pub(crate) struct TileSetMut<'a> {
+    words: &'a mut [Word],
+}
+
+impl<'a> TileSetMut<'a> {
+    pub(crate) fn new(words: &'a mut [Word]) -> Self {
+        return Self { words };
+    }
+
+    /// Adds the tiles `0..num_tiles` and removes every other tile
+    pub(crate) fn fill(&mut self, num_tiles: usize) {
+        for (i, word) in self.words.iter_mut().enumerate() {
+            let start = i * WORD_BITS;
+            *word = match num_tiles.saturating_sub(start) {
+                0 => 0,
+                n if n >= WORD_BITS => Word::MAX,
+                n => (1 << n) - 1,
+            };
+        }
+    }
+
+    /// Adds a tile. Returns whether it was already in the set
+    pub(crate) fn insert(&mut self, id: TileId) -> bool {
+        let word = &mut self.words[id / WORD_BITS];
+        let bit = 1 << (id % WORD_BITS);
+        let contained = *word & bit != 0;
+        *word |= bit;
+        return contained;
+    }
+
+    /// Removes a tile. Returns whether it was in the set
+    pub(crate) fn remove(&mut self, id: TileId) -> bool {
+        let word = &mut self.words[id / WORD_BITS];
+        let bit = 1 << (id % WORD_BITS);
+        let contained = *word & bit != 0;
+        *word &= !bit;
+        return contained;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tile_set_spans_words() {
+        let mut words = vec![0; TileSet::words_for(70)];
+        assert_eq!(words.len(), 2);
+        let mut set = TileSetMut::new(&mut words);
+        set.fill(70);
+        assert!(set.remove(64));
+        assert!(!set.remove(64));
+        assert!(set.remove(3));
+        let set = TileSet::new(&words);
+        assert_eq!(set.len(), 68);
+        assert!(!set.contains(3) && !set.contains(64));
+        assert!(set.contains(69) && !set.contains(70));
+        assert_eq!(set.iter().count(), 68);
+        assert_eq!(set.iter().take(4).collect::<Vec<_>>(), vec![0, 1, 2, 4]);
+        assert_eq!(set.iter().last(), Some(69));
+    }
+}
@@ -0,0 +1,343 @@
+//! Solving 3D grids of voxels with six direction adjacency
+//!
+//! [`VoxelModel`] is a [`Model`] over [`Dir3`] instead of [`CardinalDirs`], so it has the
+//! same propagation, backtracking, constraints and heuristics as the 2D model
+//!
+//! [`CardinalDirs`]: crate::adjacency_rules::CardinalDirs
+
+use glam::{BVec3, IVec3, UVec3};
+use std::ops::{Add, Index, IndexMut, Neg};
+
+use crate::{
+    adjacency_rules::{AdjacencyRules, Direction},
+    tile::TileId,
+    wfc::Model,
+};
+/// The six directions between neighboring voxels. `y` points up, `z` points south
+/// and `x` points east
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dir3 {
+    Up,
+    Down,
+    North,
+    South,
+    East,
+    West,
+}
+
+impl Dir3 {
+    pub fn as_array() -> [Self; 6] {
+        return [
+            Self::Up,
+            Self::Down,
+            Self::North,
+            Self::South,
+            Self::East,
+            Self::West,
+        ];
+    }
+
+    /// North, South, East and West
+    pub fn horizontal() -> [Self; 4] {
+        return [Self::North, Self::South, Self::East, Self::West];
+    }
+}
+
+impl Neg for Dir3 {
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        match self {
+            Dir3::Up => Self::Down,
+            Dir3::Down => Self::Up,
+            Dir3::North => Self::South,
+            Dir3::South => Self::North,
+            Dir3::East => Self::West,
+            Dir3::West => Self::East,
+        }
+    }
+}
+
+impl From<Dir3> for usize {
+    fn from(value: Dir3) -> Self {
+        match value {
+            Dir3::Up => 0,
+            Dir3::Down => 1,
+            Dir3::North => 2,
+            Dir3::South => 3,
+            Dir3::East => 4,
+            Dir3::West => 5,
+        }
+    }
+}
+
+impl From<Dir3> for IVec3 {
+    fn from(value: Dir3) -> Self {
+        match value {
+            Dir3::Up => IVec3::Y,
+            Dir3::Down => IVec3::NEG_Y,
+            Dir3::North => IVec3::NEG_Z,
+            Dir3::South => IVec3::Z,
+            Dir3::East => IVec3::X,
+            Dir3::West => IVec3::NEG_X,
+        }
+    }
+}
+
+impl Add<IVec3> for Dir3 {
+    type Output = IVec3;
+
+    fn add(self, rhs: IVec3) -> Self::Output {
+        let dir: IVec3 = self.into();
+        return rhs + dir;
+    }
+}
+
+impl<T> Index<Dir3> for [T; 6] {
+    type Output = T;
+
+    fn index(&self, index: Dir3) -> &Self::Output {
+        let index: usize = index.into();
+        return &self[index];
+    }
+}
+
+impl<T> IndexMut<Dir3> for [T; 6] {
+    fn index_mut(&mut self, index: Dir3) -> &mut Self::Output {
+        let index: usize = index.into();
+        return &mut self[index];
+    }
+}
+
+impl Direction for Dir3 {
+    type Loc = UVec3;
+    type Axes = BVec3;
+    type Array<T> = [T; 6];
+
+    fn all() -> [Self; 6] {
+        return Self::as_array();
+    }
+
+    fn array_from_fn<T>(f: impl FnMut(Self) -> T) -> [T; 6] {
+        return Self::as_array().map(f);
+    }
+
+    fn bounded() -> BVec3 {
+        return BVec3::FALSE;
+    }
+
+    fn num_cells(dims: UVec3) -> usize {
+        return dims.x as usize * dims.y as usize * dims.z as usize;
+    }
+
+    fn inbounds(loc: UVec3, dims: UVec3) -> bool {
+        return loc.cmplt(dims).all();
+    }
+
+    #[inline]
+    fn index(loc: UVec3, dims: UVec3) -> usize {
+        return ((loc.z * dims.y + loc.y) * dims.x + loc.x) as usize;
+    }
+
+    fn loc(index: usize, dims: UVec3) -> UVec3 {
+        let index = index as u32;
+        let x = index % dims.x;
+        let y = index / dims.x % dims.y;
+        let z = index / (dims.x * dims.y);
+        return UVec3::new(x, y, z);
+    }
+
+    fn neighbor(self, loc: UVec3, dims: UVec3, periodic: BVec3) -> Option<UVec3> {
+        let dims = dims.as_ivec3();
+        let neighbor = self + loc.as_ivec3();
+        let wrapped = IVec3::new(
+            neighbor.x.rem_euclid(dims.x),
+            neighbor.y.rem_euclid(dims.y),
+            neighbor.z.rem_euclid(dims.z),
+        );
+        let neighbor = IVec3::select(periodic, wrapped, neighbor);
+        if neighbor.cmplt(IVec3::ZERO).any() || neighbor.cmpge(dims).any() {
+            return None;
+        }
+        return Some(neighbor.as_uvec3());
+    }
+}
+
+/// The tiles allowed next to each tile in each of the six directions
+pub type VoxelRules = AdjacencyRules<Dir3>;
+
+impl AdjacencyRules<Dir3> {
+    /// Allows `from` and `to` next to each other in every horizontal direction
+    pub fn allow_horizontal(&mut self, from: TileId, to: TileId) {
+        for dir in Dir3::horizontal() {
+            self.allow(from, to, dir);
+        }
+    }
+}
+
+/// Wave function collapse over a 3D grid, e.g. for building interiors or voxel terrain
+pub type VoxelModel = Model<Dir3>;
+
+/// A small terrain tileset of ground topped with a layer of grass under air. Neighboring
+/// columns of ground differ in height by at most one voxel
+pub mod terrain {
+    use super::{Dir3, VoxelRules};
+    use crate::tile::Weight;
+
+    pub const AIR: usize = 0;
+    pub const GROUND: usize = 1;
+    pub const GRASS: usize = 2;
+
+    pub fn rules() -> VoxelRules {
+        let mut rules = VoxelRules::new();
+        rules.allow(AIR, AIR, Dir3::Up);
+        rules.allow(GROUND, GROUND, Dir3::Up);
+        rules.allow(GROUND, GRASS, Dir3::Up);
+        rules.allow(GRASS, AIR, Dir3::Up);
+
+        rules.allow_horizontal(AIR, AIR);
+        rules.allow_horizontal(GROUND, GROUND);
+        rules.allow_horizontal(GRASS, GRASS);
+        // steps up and down between neighboring columns
+        rules.allow_horizontal(GRASS, AIR);
+        rules.allow_horizontal(GRASS, GROUND);
+        return rules;
+    }
+
+    pub fn frequencies() -> Vec<Weight> {
+        return vec![4.0, 4.0, 1.0];
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::terrain::{AIR, GRASS, GROUND};
+    use super::*;
+    use crate::{
+        heuristic::CellHeuristic,
+        wfc::{Backtracking, Cell, WfcError},
+    };
+    use std::cmp::Ordering;
+
+    fn terrain_model(dims: UVec3, seed: u64) -> VoxelModel {
+        return VoxelModel::from_seed(terrain::rules(), terrain::frequencies(), dims, seed);
+    }
+
+    fn tile_at(model: &VoxelModel, loc: UVec3) -> Option<TileId> {
+        return model.get_cell(loc).and_then(|cell| cell.collapsed_to);
+    }
+
+    fn assert_follows_rules(model: &VoxelModel) {
+        let rules = terrain::rules();
+        let dims = model.dims();
+        for cell in model.iter_cells() {
+            let tile = cell.collapsed_to.unwrap();
+            for dir in Dir3::as_array() {
+                let Some(neighbor) = dir.neighbor(cell.loc, dims, BVec3::FALSE) else {
+                    continue;
+                };
+                let neighbor_tile = tile_at(model, neighbor).unwrap();
+                assert!(
+                    rules.is_allowed(tile, neighbor_tile, dir),
+                    "{neighbor_tile} {dir:?} of {tile} at {}",
+                    cell.loc
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn neighbors_follow_rules() {
+        let mut model = terrain_model(UVec3::new(8, 6, 8), 0);
+        model.solve(0, 20).unwrap();
+        assert_follows_rules(&model);
+    }
+
+    #[test]
+    fn backtracking_solves_voxels() {
+        let mut model =
+            terrain_model(UVec3::new(8, 6, 8), 3).with_backtracking(Backtracking::default());
+        model.run().unwrap();
+        assert_follows_rules(&model);
+    }
+
+    #[test]
+    fn fixed_tiles_shape_the_terrain() {
+        let dims = UVec3::new(6, 5, 6);
+        let mut model = terrain_model(dims, 1);
+        model.set_tile(UVec3::new(2, 2, 3), GRASS).unwrap();
+        model.solve(1, 20).unwrap();
+        // the grass is on top of a column of ground under a column of air
+        for y in 0..dims.y {
+            let expected = match y.cmp(&2) {
+                Ordering::Less => GROUND,
+                Ordering::Equal => GRASS,
+                Ordering::Greater => AIR,
+            };
+            assert_eq!(tile_at(&model, UVec3::new(2, y, 3)), Some(expected));
+        }
+    }
+
+    #[test]
+    fn conflicting_fixed_tiles_fail() {
+        let mut model = terrain_model(UVec3::splat(4), 0);
+        model.set_tile(UVec3::new(1, 1, 1), GRASS).unwrap();
+        // only air is allowed above grass
+        let err = model.set_tile(UVec3::new(1, 2, 1), GRASS);
+        assert_eq!(
+            err,
+            Err(WfcError::Contradiction {
+                loc: UVec3::new(1, 2, 1)
+            })
+        );
+        assert!(model.run().is_err());
+        // the conflict is found again on every reset
+        assert!(model.solve(0, 3).is_err());
+    }
+
+    /// Gives every voxel a NaN priority
+    #[derive(Debug)]
+    struct NanPriority;
+
+    impl CellHeuristic<UVec3> for NanPriority {
+        fn priority(&self, _cell: &Cell<UVec3>, _dims: UVec3) -> f32 {
+            return f32::NAN;
+        }
+    }
+
+    #[test]
+    fn nan_priorities_still_collapse_every_voxel() {
+        let mut model = terrain_model(UVec3::splat(4), 2).with_heuristic(NanPriority);
+        model.solve(2, 20).unwrap();
+        assert_follows_rules(&model);
+    }
+
+    #[test]
+    fn tiles_without_rules_are_kept_out_of_cells_with_neighbors() {
+        // tile 3 has a frequency but isn't allowed next to anything
+        let mut frequencies = terrain::frequencies();
+        frequencies.push(100.0);
+        let mut model =
+            VoxelModel::from_seed(terrain::rules(), frequencies.clone(), UVec3::splat(3), 4);
+        model.solve(4, 20).unwrap();
+        assert!(model.iter_cells().all(|cell| cell.collapsed_to != Some(3)));
+
+        // a single voxel has no neighbors to disallow it
+        let mut model = VoxelModel::from_seed(terrain::rules(), frequencies, UVec3::ONE, 4);
+        model.set_tile(UVec3::ZERO, 3).unwrap();
+        model.run().unwrap();
+    }
+
+    #[test]
+    fn same_seed_same_voxels() {
+        let dims = UVec3::splat(6);
+        let mut a = terrain_model(dims, 5);
+        let mut b = terrain_model(dims, 5);
+        a.solve(5, 20).unwrap();
+        b.solve(5, 20).unwrap();
+        let tiles = |model: &VoxelModel| -> Vec<Option<TileId>> {
+            return model.iter_cells().map(|cell| cell.collapsed_to).collect();
+        };
+        assert_eq!(tiles(&a), tiles(&b));
+    }
+}
@@ -0,0 +1,90 @@
+//! Per cell tile weights, see [`Model::with_tile_weights`](crate::wfc::Model::with_tile_weights)
+
+use glam::UVec2;
+use image::GrayImage;
+use std::fmt::Debug;
+
+use crate::tile::{IdMap, TileId, Weight};
+
+/// Decides the weight of each tile in each cell
+///
+/// The weights of the tiles still allowed in a cell are used both to pick the tile
+/// it collapses to and to calculate its entropy. The weight of a tile in a cell must not
+/// change on its own, the entropy of each cell is updated as its tiles are removed.
+/// Use [`Model::set_weights`](crate::wfc::Model::set_weights) to change weights mid solve
+pub trait TileWeights<L = UVec2>: Debug + Send + Sync {
+    /// The weight of `tile` in the cell at `loc`. `frequency` is the global weight
+    /// of the tile, i.e. its frequency in the input or the weight it was overridden with
+    fn weight(&self, loc: L, tile: TileId, frequency: Weight) -> Weight;
+}
+
+/// Uses the global weight of every tile in every cell. This is the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Frequencies;
+
+impl<L> TileWeights<L> for Frequencies {
+    fn weight(&self, _loc: L, _tile: TileId, frequency: Weight) -> Weight {
+        return frequency;
+    }
+}
+
+/// Scales the weights of tiles by grayscale images stretched over the output
+///
+/// The weight of a tile is its global weight scaled by the brightness of its map
+/// at the cell, from 0 for black to 1 for white. Tiles without a map keep their global
+/// weight. A black pixel means the tile is only picked when every allowed tile has a
+/// weight of zero
+#[derive(Debug, Clone, Default)]
+pub struct WeightMap {
+    /// the output dimensions in cells
+    dims: UVec2,
+    maps: IdMap<Option<GrayImage>>,
+}
+
+impl WeightMap {
+    pub fn new(dims: UVec2) -> Self {
+        return Self {
+            dims,
+            maps: Vec::new(),
+        };
+    }
+
+    /// Sets the map of `tile`. The map doesn't need to be the same size as the output
+    pub fn with_map(mut self, tile: TileId, map: GrayImage) -> Self {
+        if self.maps.len() <= tile {
+            self.maps.resize(tile + 1, None);
+        }
+        self.maps[tile] = Some(map);
+        return self;
+    }
+}
+
+impl TileWeights for WeightMap {
+    fn weight(&self, loc: UVec2, tile: TileId, frequency: Weight) -> Weight {
+        let Some(Some(map)) = self.maps.get(tile) else {
+            return frequency;
+        };
+        // the pixel under the cell when the map is stretched over the output
+        let x = (loc.x as u64 * map.width() as u64 / self.dims.x as u64) as u32;
+        let y = (loc.y as u64 * map.height() as u64 / self.dims.y as u64) as u32;
+        let brightness = map.get_pixel(x, y)[0];
+        return frequency * brightness as Weight / u8::MAX as Weight;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn weight_map_is_stretched_over_output() {
+        // left half black, right half white
+        let map = GrayImage::from_fn(2, 1, |x, _| Luma([if x == 0 { 0 } else { 255 }]));
+        let weights = WeightMap::new(UVec2::new(10, 4)).with_map(1, map);
+        assert_eq!(weights.weight(UVec2::new(4, 3), 1, 2.0), 0.0);
+        assert_eq!(weights.weight(UVec2::new(5, 0), 1, 2.0), 2.0);
+        // tiles without a map keep their frequency
+        assert_eq!(weights.weight(UVec2::new(4, 3), 0, 2.0), 2.0);
+    }
+}
@@ -1,20 +1,24 @@
 use std::{
-    cmp::{Ordering, Reverse},
-    collections::{BinaryHeap, hash_map::RandomState},
-    iter::{repeat, zip},
+    cmp::Ordering,
+    collections::{hash_map::RandomState, VecDeque},
+    iter::zip,
     ops::{Index, IndexMut},
+    sync::Arc,
 };
 
 use derive_more::{Deref, DerefMut};
 use glam::{IVec2, UVec2};
 
 use crate::{
-    adjacency_rules::{AdjacencyRules, CardinalDirs, EnablerDict},
+    adjacency_rules::{AdjacencyRules, AdjacencyTable, CardinalDirs, Direction},
+    heuristic::{CellHeuristic, MinEntropy},
     preprocessor::Pattern,
-    tile::{IdMap, TileId},
+    tile::{IdMap, TileId, TileSet, TileSetMut, Weight, Word},
+    weights::{Frequencies, TileWeights},
     Area, Grid,
 };
 use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
 // TODO: rename all "tile" to "pattern" and "cell" to "tile"
 
 /// A Cell corresponds to a pattern in the output image
@@ -22,23 +26,32 @@ use rand::prelude::*;
 /// the input image
 /// When the number of possible tiles is 1 the cell is considered to
 /// be "collapsed" and in it's final state
+///
+/// The possible tiles and their enabler counts are stored on the [`Board`] so
+/// they can be packed into flat arrays, [`CellView`] pairs a cell with its tiles
 #[derive(Debug, Clone)]
-pub struct Cell {
+pub struct Cell<L = UVec2> {
     pub collapsed_to: Option<TileId>,
-    pub domain: EnablerDict,
     pub probability_dict: ProbabilityDict,
-    pub loc: UVec2,
+    pub loc: L,
     pub random_entropy: f32,
+    /// the number of tiles left in the domain
+    num_possible: usize,
 }
 
-impl Cell {
-    fn new(probability_dict: ProbabilityDict, enabler_dict: EnablerDict, loc: UVec2, random_entropy: f32) -> Self {
+impl<L> Cell<L> {
+    fn new(
+        probability_dict: ProbabilityDict,
+        num_possible: usize,
+        loc: L,
+        random_entropy: f32,
+    ) -> Self {
         return Self {
             collapsed_to: None,
-            domain: enabler_dict,
             probability_dict,
             loc,
-            random_entropy
+            random_entropy,
+            num_possible,
         };
     }
 
@@ -46,64 +59,67 @@ impl Cell {
         return self.collapsed_to.is_some();
     }
 
-    fn choose_collapse_tile(&self) -> TileId {
-        if self.collapsed() {
-            unreachable!("Cell has already been collapsed");
-        }
-        let mut rng = rand::thread_rng();
-        return self
-            .domain
-            .allowed_tile_ids()
-            .choose(&mut rng)
-            .expect("cell has possible tiles");
+    /// The number of tiles that are still possible
+    pub fn num_possible(&self) -> usize {
+        return self.num_possible;
     }
 
-    fn collapse(&mut self) -> Vec<TileRemovalEvent> {
-        let fin: TileId = self.choose_collapse_tile();
-        self.collapsed_to = Some(fin);
-        let removed_tile_ids = self.domain.remove_all_but(fin);
-        let tile_removed_events =
-            TileRemovalEvent::from_list_of_removed_tiles(removed_tile_ids, self.loc);
-        return tile_removed_events;
+    /// Whether a single tile is left, even if the cell hasn't been collapsed to it yet
+    pub fn is_decided(&self) -> bool {
+        return self.num_possible == 1;
     }
 
-    fn remove_enabler(
-        &mut self,
-        enabler: TileId,
-        from_dir: CardinalDirs,
-        adjacency_rules: &AdjacencyRules,
-    ) -> Option<Vec<TileRemovalEvent>> {
-        if self.collapsed() {
-            // assert!(adjacency_rules.enabled_by(enabler, from_dir).contains(&self.collapsed_to.unwrap()), "Contradiction: trying to remove enabler {enabler} which enables {} which this cell is collapsed to", self.collapsed_to.unwrap());
-            // log::warn!("Contradiction: tried to remove enabler: {enabler} from cell that was collapsed to that tile");
-            return None;
-        }
-        let removed_tiles = self
-            .domain
-            .remove_single(enabler, from_dir, adjacency_rules)?;
-        for &tile in &removed_tiles {
-            self.probability_dict.remove(tile);
-        }
-        let events = TileRemovalEvent::from_list_of_removed_tiles(removed_tiles, self.loc);
-        return Some(events);
+    fn get_entropy_entry(
+        &self,
+        heuristic: &dyn CellHeuristic<L>,
+        dims: L,
+        index: usize,
+    ) -> EntropyEntry {
+        return EntropyEntry {
+            entropy: heuristic.priority(self, dims),
+            index,
+        };
+    }
+}
+
+/// A [`Cell`] together with the tiles that are still possible in it
+#[derive(Debug, Clone, Copy)]
+pub struct CellView<'a, L = UVec2> {
+    cell: &'a Cell<L>,
+    pub domain: TileSet<'a>,
+    weights: &'a Weights<L>,
+}
+
+impl<'a, L> std::ops::Deref for CellView<'a, L> {
+    type Target = Cell<L>;
+
+    fn deref(&self) -> &Self::Target {
+        return self.cell;
+    }
+}
+
+impl<'a, L: Copy> CellView<'a, L> {
+    /// The weight of a tile used to choose between the possible tiles
+    pub fn weight(&self, id: TileId) -> Weight {
+        return self.weights.get(self.cell.loc, id);
     }
 
     // TODO: Move this too window
-    pub fn render(&self, patterns: &Vec<Pattern>, tile_size: usize) -> Pattern {
-        let allowed_patterns: Vec<(TileId, Pattern)> =
-            self.domain.filter_allowed_enumerate(patterns).collect();
+    pub fn render(&self, patterns: &[Pattern], tile_size: usize) -> Pattern {
+        let allowed_patterns: Vec<(TileId, &Pattern)> =
+            self.domain.iter().map(|id| (id, &patterns[id])).collect();
         if allowed_patterns.len() == 1 {
             return allowed_patterns[0].1.to_owned();
         }
         return allowed_patterns
             .iter()
-            .map(|(id, p)| -> Vec<[usize; 4]> {
-                let count: usize = self.probability_dict.counts[*id];
+            .map(|(id, p)| -> Vec<[Weight; 4]> {
+                let weight = self.weight(*id);
                 p.iter()
-                    .map(|pixel| pixel.map(|channel| channel as usize * count))
+                    .map(|pixel| pixel.map(|channel| channel as Weight * weight))
                     .collect()
             })
-            .fold(vec![[0; 4]; tile_size * tile_size], |acc, pat| {
+            .fold(vec![[0.0; 4]; tile_size * tile_size], |acc, pat| {
                 zip(acc, pat)
                     .map(|(acc_pix, pat_pix)| {
                         [
@@ -124,429 +140,3315 @@ impl Cell {
             })
             .collect();
     }
+}
 
-    fn get_entropy_entry(&self) -> EntropyEntry {
-        return EntropyEntry {
-            entropy: self.probability_dict.entropy() + self.random_entropy,
-            loc: self.loc,
-        };
+/// The source of every random decision made by a [`Model`]
+///
+/// Wraps any user supplied [`RngCore`] so the model doesn't need to be
+/// generic over the rng type
+pub struct WfcRng(Box<dyn RngCore + Send>);
+
+impl WfcRng {
+    pub fn new(rng: impl RngCore + Send + 'static) -> Self {
+        return Self(Box::new(rng));
     }
-}
 
-#[derive(Debug, Default)]
-pub struct Model {
-    entropy_heap: MinEntropyHeap,
-    adjacency_rules: AdjacencyRules,
-    board: Board,
-    // tile_frequencies: IdMap<usize>,
-    // dims: UVec2,
-    wave: Vec<TileRemovalEvent>,
-    pub remaining_uncollapsed: u32,
-    updated_cells: Vec<UVec2>,
-    // tile_size: usize,
+    /// A portable rng that produces the same sequence on every platform
+    /// (including wasm32) for a given seed
+    pub fn from_seed(seed: u64) -> Self {
+        return Self::new(ChaCha8Rng::seed_from_u64(seed));
+    }
 }
 
-impl Model {
-    pub fn new(adjacency_rules: AdjacencyRules, tile_frequencies: Vec<usize>, dims: UVec2) -> Self {
-        let grid = Grid(dims);
-        let num_cells = grid.area();
-
-        // TODO: consider just initializing these in  cell init
-        // for cleanliness
-        let probability_dict = ProbabilityDict::new(&tile_frequencies);
-        let mut entropy_heap = MinEntropyHeap::new();
-        let enabler_dict = EnablerDict::new(&adjacency_rules);
+impl RngCore for WfcRng {
+    fn next_u32(&mut self) -> u32 {
+        return self.0.next_u32();
+    }
 
-        let mut cells = Vec::with_capacity(num_cells as usize);
+    fn next_u64(&mut self) -> u64 {
+        return self.0.next_u64();
+    }
 
-        for (i, loc) in grid.iter_locs().enumerate() {
-            let random_entropy = rand::thread_rng().gen();
-            let cell = Cell::new(probability_dict.clone(), enabler_dict.clone(), loc, random_entropy);
-            entropy_heap.push(cell.get_entropy_entry());
-            cells.push(cell);
-        }
-        let board = Board { grid, vals: cells };
-        let updated_cells = Vec::with_capacity(num_cells as usize);
-        return Self {
-            adjacency_rules,
-            updated_cells,
-            // tile_frequencies,
-            board,
-            // dims,
-            entropy_heap,
-            remaining_uncollapsed: num_cells,
-            ..Default::default()
-        };
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.0.fill_bytes(dest);
     }
 
-    pub fn get_cell_mut(&mut self, loc: UVec2) -> Option<&mut Cell> {
-        return self.board.get_cell_mut(loc.as_ivec2());
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        return self.0.try_fill_bytes(dest);
     }
+}
 
-    pub fn get_cell(&self, loc: UVec2) -> Option<&Cell> {
-        return self.board.get_cell(loc.as_ivec2());
+impl std::fmt::Debug for WfcRng {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WfcRng").finish_non_exhaustive()
     }
+}
 
-    pub fn get_cell_to_collapse(&mut self) -> Option<UVec2> {
-        if self.remaining_uncollapsed == 0 {
-            return None;
-        }
-        while let Some(entry) = self.entropy_heap.pop() {
-            let cell = self
-                .get_cell(entry.loc)
-                .expect("entropy heap entries should all be inbounds");
-            if !cell.collapsed() {
-                return Some(cell.loc);
+/// The ways running a [`Model`] can fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WfcError<L = UVec2> {
+    /// The cell at `loc` has no possible tiles left, i.e. the tiles of its
+    /// neighbors can not all be satisfied
+    Contradiction { loc: L },
+    /// [`Model::solve`] hit a contradiction on every attempt
+    AttemptsExhausted { attempts: u32 },
+    /// `tile` can no longer be placed in a number of cells within its [`TileCount`]
+    TileCount { tile: TileId },
+    /// The cell at `loc` must be walkable but can't be connected to the other
+    /// walkable cells, see [`Connectivity`]
+    Disconnected { loc: L },
+    /// The cell at `loc` was constrained with a tile id the model has no tile for
+    UnknownTile { loc: L, tile: TileId },
+}
+
+impl<L: std::fmt::Display> std::fmt::Display for WfcError<L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WfcError::Contradiction { loc } => {
+                write!(f, "contradiction: cell at {loc} has no possible tiles")
+            }
+            WfcError::AttemptsExhausted { attempts } => {
+                write!(f, "no solution found in {attempts} attempts")
+            }
+            WfcError::TileCount { tile } => {
+                write!(
+                    f,
+                    "contradiction: tile {tile} can not be placed within its count limits"
+                )
+            }
+            WfcError::Disconnected { loc } => {
+                write!(
+                    f,
+                    "contradiction: cell at {loc} is cut off from the other walkable cells"
+                )
+            }
+            WfcError::UnknownTile { loc, tile } => {
+                write!(
+                    f,
+                    "cell at {loc} was given tile {tile} which is not in the model"
+                )
             }
         }
-
-        unreachable!("Entropy Heap should never be empty");
     }
+}
 
-    pub fn collapse_cell(&mut self) {
-        if let Some(loc) = self.get_cell_to_collapse() {
-            log::info!("Collapsing Cell at {loc:?}");
-            let tile_removed_events = {
-                let cell = &mut self
-                    .get_cell_mut(loc)
-                    .expect("entropy heap entries should all be inbounds");
-                cell.collapse()
-            };
+impl<L: std::fmt::Debug + std::fmt::Display> std::error::Error for WfcError<L> {}
 
-            self.wave = tile_removed_events;
-            self.remaining_uncollapsed -= 1;
-            self.updated_cells.push(loc);
-            log::trace!(
-                "Collapsed cell {:?}. Removed {}/{} tile options",
-                loc,
-                self.wave.len(),
-                self.adjacency_rules.len()
-            );
-        }
-    }
+/// Two neighboring cells fixed by [`Model::from_grid`] to tiles that the adjacency
+/// rules don't allow next to each other. `b` is to the right of or below `a`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Conflict {
+    pub a: UVec2,
+    pub b: UVec2,
+}
 
-    pub fn propogate(&mut self) {
-        match self.wave.pop() {
-            Some(event) => {
-                log::info!(
-                    "Propogating removal of tile {} from cell at {:?}",
-                    event.tile_id,
-                    event.cell_loc
-                );
-                assert!(self.board.inbounds(event.cell_loc.as_ivec2()));
+/// The ways the fixed cells passed to [`Model::from_grid`] can fail
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GridError {
+    /// Every pair of neighboring fixed cells that conflict with each other
+    Conflicts(Vec<Conflict>),
+    /// The fixed cells don't conflict with their neighbors but propogating them
+    /// leaves a cell without any possible tiles, or a fixed cell has an unknown tile
+    Unsatisfiable(WfcError),
+}
 
-                let adjacent_tile_locs = self.board.cardinal_neighbors(event.cell_loc);
-                for (dir, adjacent_tile_loc) in adjacent_tile_locs {
-                    if !self.board.inbounds(adjacent_tile_loc) {
-                        continue;
-                    }
-                    log::trace!(
-                        "{:?} -> {:?} -> {:?}",
-                        event.cell_loc,
-                        dir,
-                        adjacent_tile_loc
-                    );
-                    let adj_cell = self
-                        .board
-                        .get_cell_mut(adjacent_tile_loc)
-                        .expect("adjacent tile is inbounds");
-                    if let Some(tile_removed_events) =
-                        adj_cell.remove_enabler(event.tile_id, dir, &self.adjacency_rules)
-                    {
-                        log::trace!("removed {} options", tile_removed_events.len());
-                        for event in tile_removed_events {
-                            self.wave.push(event);
-                        }
-                        self.updated_cells.push(adjacent_tile_loc.as_uvec2());
-                    }
-                    let entropy_entry = adj_cell.get_entropy_entry();
-                    self.entropy_heap.push(entropy_entry);
+impl std::fmt::Display for GridError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GridError::Conflicts(conflicts) => {
+                write!(f, "{} pairs of fixed cells conflict:", conflicts.len())?;
+                for Conflict { a, b } in conflicts {
+                    write!(f, " {a} - {b}")?;
                 }
+                return Ok(());
+            }
+            GridError::Unsatisfiable(contradiction) => {
+                write!(f, "fixed cells can not be completed: {contradiction}")
             }
-            None => unreachable!("If wave was empty we should have collapsed a cell instead"),
         }
     }
+}
 
-    /// steps the model one iteration by either collapsing or propogating TileRemovalEvents
-    pub fn step(&mut self) -> Vec<UVec2> {
-        // no tiles left to collapse -> done
-        if self.remaining_uncollapsed == 0 {
-            for cell in self.iter_cells() {
-                assert!(cell.domain.allowed_tile_ids().count() == 1);
-            }
-            return Vec::new();
-        }
-        // stack empty -> need to collapse a tile
-        if self.wave.is_empty() {
-            self.collapse_cell();
-        } else {
-            self.propogate();
-        }
+impl std::error::Error for GridError {}
 
-        let mut updated_cells: Vec<UVec2> = Vec::with_capacity(self.updated_cells.len());
-        // NOTE: append moves the values out of self.updated_cells and into the new vec that is
-        // returned
-        updated_cells.append(&mut self.updated_cells);
-        return updated_cells;
-    }
+/// Limits for the backtracking search enabled by [`Model::with_backtracking`]
+#[derive(Debug, Clone, Copy)]
+pub struct Backtracking {
+    /// The max number of collapse decisions that can be undone. Each decision keeps the
+    /// changes made to the board after it so this bounds the memory used. When the stack
+    /// is full the oldest decision is forgotten
+    pub max_depth: usize,
+    /// The max number of times the model backtracks before giving up on the
+    /// contradiction
+    pub max_backtracks: usize,
+}
 
-    pub fn iter_cells(&self) -> impl Iterator<Item = &Cell> {
-        return self.board.iter();
+impl Default for Backtracking {
+    fn default() -> Self {
+        return Self {
+            max_depth: 64,
+            max_backtracks: 10_000,
+        };
     }
 }
 
-#[derive(Debug, Deref, DerefMut, Default)]
-struct Board {
-    grid: Grid,
-    #[deref_mut]
-    #[deref]
-    vals: Vec<Cell>,
+/// The tiles allowed in the cells along each side of the output, applied with
+/// [`Model::with_border`]. Sides without a constraint allow every tile
+#[derive(Debug, Clone, Default)]
+pub struct Border {
+    allowed: [Option<Vec<TileId>>; 4],
 }
 
-impl Board {
-    #[inline]
-    fn index_grid(&self, loc: UVec2) -> usize {
-        return (loc.y * self.grid.x + loc.x) as usize;
-    }
-    pub fn inbounds(&self, loc: IVec2) -> bool {
-        return loc.cmpge(IVec2::ZERO).all() && loc.cmplt(self.grid.as_ivec2()).all();
+impl Border {
+    pub fn new() -> Self {
+        return Self::default();
     }
 
-    pub fn cardinal_neighbors(&self, loc: UVec2) -> [(CardinalDirs, IVec2); 4] {
-        let loc = loc.as_ivec2();
-        let neighbors = [
-            CardinalDirs::Up,
-            CardinalDirs::Right,
-            CardinalDirs::Down,
-            CardinalDirs::Left,
-        ]
-        .map(|dir| (dir, dir + loc));
-        return neighbors;
+    /// Only allows `tiles` in the cells along `side` of the output
+    pub fn with_side(
+        mut self,
+        side: CardinalDirs,
+        tiles: impl IntoIterator<Item = TileId>,
+    ) -> Self {
+        self.allowed[side] = Some(tiles.into_iter().collect());
+        return self;
     }
 
-    pub fn get_cell(&self, loc: IVec2) -> Option<&Cell> {
-        if !self.inbounds(loc) {
-            return None;
-        }
-        return Some(&self[loc]);
+    /// Treats everything outside the output as the virtual `outside` tile, so only the tiles
+    /// the rules allow next to it can be on each side.
+    ///
+    /// `rules` only needs the entries for `outside`. Keep them separate from the rules
+    /// passed to the model so the outside tile is never placed in the output
+    pub fn from_outside(rules: &AdjacencyRules, outside: TileId) -> Self {
+        return Self {
+            allowed: CardinalDirs::as_array().map(|side| Some(rules.enabled_by(outside, -side))),
+        };
     }
+}
 
-    pub fn get_cell_mut(&mut self, loc: IVec2) -> Option<&mut Cell> {
-        if !self.inbounds(loc) {
-            return None;
-        }
-        return Some(&mut self[loc]);
-    }
+/// Limits on the number of cells of the output a tile is placed in, applied with
+/// [`Model::with_tile_count`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileCount {
+    pub min: u32,
+    pub max: u32,
 }
 
-impl Index<IVec2> for Board {
-    type Output = Cell;
+impl TileCount {
+    pub fn exactly(count: u32) -> Self {
+        return Self {
+            min: count,
+            max: count,
+        };
+    }
 
-    fn index(&self, index: IVec2) -> &Self::Output {
-        let i = self.index_grid(index.as_uvec2());
-        return &self.vals[i];
+    pub fn at_least(min: u32) -> Self {
+        return Self { min, max: u32::MAX };
     }
-}
 
-impl IndexMut<IVec2> for Board {
-    fn index_mut(&mut self, index: IVec2) -> &mut Self::Output {
-        let i = self.index_grid(index.as_uvec2());
-        return &mut self.vals[i];
+    pub fn at_most(max: u32) -> Self {
+        return Self { min: 0, max };
+    }
+
+    /// At least `fraction` of the cells of an output with the given dimensions
+    pub fn at_least_fraction(fraction: f64, dims: UVec2) -> Self {
+        let min = (fraction * Grid(dims).area() as f64).ceil() as u32;
+        return Self::at_least(min);
     }
 }
 
+/// Tiles that must form a single connected region of the output, e.g. paths or floors,
+/// applied with [`Model::with_connectivity`]
+///
+/// Two neighboring cells are connected when both are walkable and both of their
+/// tiles have an exit on the side facing the other
 #[derive(Debug, Clone)]
-pub struct ProbabilityDict {
-    pub counts: IdMap<usize>,
-    total_shannons: f32,
-    pub total_count: usize,
+pub struct Connectivity<D: Direction = CardinalDirs> {
+    /// the sides each walkable tile connects through, `None` for the other tiles
+    exits: IdMap<Option<D::Array<bool>>>,
+    check_interval: Option<u32>,
 }
 
-impl ProbabilityDict {
-    fn new(tile_frequencies: &IdMap<usize>) -> Self {
-        let counts = tile_frequencies.to_owned();
-        let total_shannons = counts.iter().map(|&freq| Self::partial_shannon(freq)).sum();
-        let total_count = tile_frequencies.iter().sum();
+impl<D: Direction> Default for Connectivity<D> {
+    fn default() -> Self {
         return Self {
-            counts,
-            total_shannons,
-            total_count,
+            exits: Vec::new(),
+            check_interval: None,
         };
     }
+}
 
-    /// one of the `w0 * log(w0)` terms in the simplified entropy equation
-    #[inline]
-    fn partial_shannon(freq: usize) -> f32 {
-        let freq = freq as f32;
-        return freq * freq.log2();
+impl<D: Direction> Connectivity<D> {
+    /// Makes `tiles` walkable, connecting through all of their sides
+    pub fn new(tiles: impl IntoIterator<Item = TileId>) -> Self {
+        let mut connectivity = Self::default();
+        for tile in tiles {
+            connectivity.set_exits(tile, D::array_from_fn(|_| true));
+        }
+        return connectivity;
     }
 
-    /// Calculates the Shannon Entropy `H(x) = -P(x)*log(P(x))`
-    ///
-    /// The Shannon Entropy For set `x` of items `x0..xn` is
-    /// `H(x) = H(x0) + H(x1) ... H(xn)`
-    ///
-    /// The Probability of an entry `xi` is `P(xi) = wi / sum(w)`
-    /// where `wi` is the weight of xi i.e. `self.counts[i]`
-    /// and `sum(w)` is the sum of the weights i.e. `self.total_count`
-    ///
-    /// The simplified Entropy Equation is then:
-    /// `H(x) = log(sum(w))- (w0*log(w0) -w1*log(w1) ... -wn*log(wn)) / sum(w)`
-    fn entropy(&self) -> f32 {
-        if self.total_count == 0 {
-            return std::f32::NAN;
+    /// Makes `tile` walkable but only connects it through `sides`, e.g. the sides
+    /// of a road tile the road leaves through
+    pub fn with_exits(mut self, tile: TileId, sides: impl IntoIterator<Item = D>) -> Self {
+        let mut exits = D::array_from_fn(|_| false);
+        for side in sides {
+            exits[side] = true;
         }
-        let total_count: f32 = self.total_count as f32;
-        // Calculate entropy using simplified shannon entropy
-        return total_count.log2() - (self.total_shannons / total_count);
+        self.set_exits(tile, exits);
+        return self;
     }
 
-    /// Returns an IdMap of the relative probabilities of each tile
-    pub fn relative_probabilities(&self) -> IdMap<f32> {
-        return zip(&self.counts, repeat(self.total_count as f32))
-            .map(|(&c, t)| (c as f32) / t)
-            .collect();
+    /// How many cells are collapsed between checks of the whole board. Each check is linear
+    /// in the size of the board, so checking less often is faster but finds cut off cells
+    /// later. The finished board is always checked. Defaults to a 64th of the cells
+    pub fn with_check_interval(mut self, collapses: u32) -> Self {
+        self.check_interval = Some(collapses.max(1));
+        return self;
     }
 
-    fn remove(&mut self, id: TileId) {
-        self.total_count -= self.counts[id];
-        self.total_shannons -= Self::partial_shannon(self.counts[id]);
-        self.counts[id] = 0;
+    fn set_exits(&mut self, tile: TileId, exits: D::Array<bool>) {
+        if self.exits.len() <= tile {
+            self.exits.resize_with(tile + 1, || None);
+        }
+        self.exits[tile] = Some(exits);
+    }
+
+    fn exits(&self, tile: TileId) -> Option<&D::Array<bool>> {
+        return self.exits.get(tile).and_then(Option::as_ref);
     }
 }
 
-#[derive(Default, Debug)]
-pub struct MinEntropyHeap(BinaryHeap<Reverse<EntropyEntry>>);
+/// The outcome of a successful [`Model::solve`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SolveReport {
+    /// The number of attempts needed, including the successful one
+    pub attempts: u32,
+    /// The seed of the successful attempt. Passing it to [`Model::from_seed`]
+    /// reproduces the result
+    pub seed: u64,
+}
 
-impl MinEntropyHeap {
-    fn push(&mut self, e: EntropyEntry) {
-        self.0.push(Reverse(e));
-    }
-    fn pop(&mut self) -> Option<EntropyEntry> {
-        // unwrap from reverse
-        // TODO: just return loc?
-        self.0.pop().map(|Reverse(entry)| entry)
-    }
-    pub fn new() -> Self {
-        return Self(BinaryHeap::new());
+/// Derives the seed for a restart from the seed passed to [`Model::solve`]
+/// using splitmix64 so consecutive attempts get uncorrelated seeds
+pub(crate) fn derive_seed(seed: u64, attempt: u32) -> u64 {
+    if attempt == 0 {
+        return seed;
     }
+    return mix_seed(seed, attempt as u64);
+}
+
+/// Mixes `salt` into `seed` with splitmix64
+pub(crate) fn mix_seed(seed: u64, salt: u64) -> u64 {
+    let mut z = seed.wrapping_add(salt.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    return z ^ (z >> 31);
 }
 
+/// A collapse decision, undone by replaying the board's trail back to `trail_start`
+#[derive(Debug)]
+struct Decision {
+    trail_start: usize,
+    remaining_uncollapsed: u32,
+    unchecked_collapses: u32,
+    /// the index of the cell that was collapsed
+    i: usize,
+    /// the tile it was collapsed to
+    tile_id: TileId,
+}
+
+/// A change to the board, recorded while there are decisions that can be undone
 #[derive(Debug, Clone, Copy)]
-struct EntropyEntry {
-    entropy: f32,
-    loc: UVec2,
+enum TrailEntry<D> {
+    /// `tile` was removed from the domain of cell `i`
+    Removed { i: usize, tile: TileId },
+    /// the support at `index` from `dir` was decremented
+    Support { dir: D, index: usize },
+    /// cell `i` was collapsed
+    Collapsed { i: usize },
 }
-impl PartialEq for EntropyEntry {
-    fn eq(&self, other: &Self) -> bool {
-        self.entropy == other.entropy
-    }
+
+/// A constraint on a single cell added with [`Model::set_tile`], [`Model::ban`] or
+/// [`Model::restrict_cells`]
+#[derive(Debug, Clone)]
+enum CellConstraint<L> {
+    Set {
+        loc: L,
+        tile: TileId,
+    },
+    Ban {
+        loc: L,
+        tile: TileId,
+    },
+    /// only the tiles in the mask are allowed, stored like the domain of a cell
+    Restrict {
+        loc: L,
+        allowed: Box<[Word]>,
+    },
 }
 
-impl Eq for EntropyEntry {}
+impl<L: Copy> CellConstraint<L> {
+    fn loc(&self) -> L {
+        let (Self::Set { loc, .. } | Self::Ban { loc, .. } | Self::Restrict { loc, .. }) = *self;
+        return loc;
+    }
 
-impl PartialOrd for EntropyEntry {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match self.entropy.partial_cmp(&other.entropy) {
-            // resolve equality by comparing location
-            Some(Ordering::Equal) => self.loc.to_array().partial_cmp(&other.loc.to_array()),
-            lt_or_gt => lt_or_gt,
-        }
+    /// The same constraint on the cell at `loc`
+    fn moved_to(&self, loc: L) -> Self {
+        return match *self {
+            Self::Set { tile, .. } => Self::Set { loc, tile },
+            Self::Ban { tile, .. } => Self::Ban { loc, tile },
+            Self::Restrict { ref allowed, .. } => Self::Restrict {
+                loc,
+                allowed: allowed.clone(),
+            },
+        };
     }
 }
 
-impl Ord for EntropyEntry {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        match self.entropy.total_cmp(&other.entropy) {
-            // resolve equality by comparing location
-            Ordering::Equal => self.loc.to_array().cmp(&other.loc.to_array()),
-            lt_or_gt => lt_or_gt,
-        }
-    }
+/// Wave function collapse over a grid with the directions `D`, i.e. [`CardinalDirs`] for 2D
+/// images and maps or [`Dir3`](crate::voxel::Dir3) for voxels
+pub struct Model<D: Direction = CardinalDirs> {
+    entropy_heap: MinEntropyHeap,
+    heuristic: Arc<dyn CellHeuristic<D::Loc>>,
+    adjacency_table: AdjacencyTable<D>,
+    board: Board<D>,
+    weights: Weights<D::Loc>,
+    // dims: UVec2,
+    wave: Vec<TileRemovalEvent<D::Loc>>,
+    pub remaining_uncollapsed: u32,
+    updated_cells: Vec<D::Loc>,
+    // tile_size: usize,
+    rng: WfcRng,
+    /// set when a step fails so the model isn't stepped in an invalid state
+    contradiction: Option<WfcError<D::Loc>>,
+    backtracking: Option<Backtracking>,
+    decisions: VecDeque<Decision>,
+    pub backtracks: usize,
+    /// reapplied every time the model is reset
+    constraints: Vec<CellConstraint<D::Loc>>,
+    count_limits: Vec<(TileId, TileCount)>,
+    connectivity: Option<Connectivity<D>>,
+    /// the number of cells collapsed since the connectivity was last checked
+    unchecked_collapses: u32,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct TileRemovalEvent {
-    pub tile_id: usize,
-    pub cell_loc: UVec2,
+impl<D: Direction> std::fmt::Debug for Model<D> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Model")
+            .field("dims", &self.board.dims)
+            .field("heuristic", &self.heuristic)
+            .field("weights", &self.weights)
+            .field("remaining_uncollapsed", &self.remaining_uncollapsed)
+            .field("contradiction", &self.contradiction)
+            .field("backtracking", &self.backtracking)
+            .field("backtracks", &self.backtracks)
+            .field("count_limits", &self.count_limits)
+            .finish_non_exhaustive()
+    }
 }
 
-impl TileRemovalEvent {
-    pub fn new(tile_id: TileId, cell_loc: UVec2) -> Self {
-        return Self { tile_id, cell_loc };
+impl<D: Direction> Model<D> {
+    /// Creates a model with a random seed. Use [`Model::from_seed`] for reproducible output
+    pub fn new(
+        adjacency_rules: AdjacencyRules<D>,
+        tile_frequencies: Vec<Weight>,
+        dims: D::Loc,
+    ) -> Self {
+        let seed = rand::thread_rng().gen();
+        return Self::from_seed(adjacency_rules, tile_frequencies, dims, seed);
     }
-    pub fn from_list_of_removed_tiles(removed_tiles: Vec<TileId>, loc: UVec2) -> Vec<Self> {
-        return removed_tiles
-            .iter()
-            .map(|&removed_tile_id| Self::new(removed_tile_id, loc))
-            .collect();
+
+    /// Creates a model whose every random decision is derived from `seed`
+    ///
+    /// The same seed and inputs produce an identical result on every platform
+    pub fn from_seed(
+        adjacency_rules: AdjacencyRules<D>,
+        tile_frequencies: Vec<Weight>,
+        dims: D::Loc,
+        seed: u64,
+    ) -> Self {
+        return Self::from_rng(
+            adjacency_rules,
+            tile_frequencies,
+            dims,
+            WfcRng::from_seed(seed),
+        );
     }
-}
 
-#[cfg(test)]
-mod test {
-    use crate::simple_patterns::{construct_simple_patterns, CHARS};
+    /// Creates a model that draws every random decision from `rng`
+    ///
+    /// There is a tile for every frequency, tiles without an entry in the rules can only be
+    /// placed in cells without neighbors
+    pub fn from_rng(
+        adjacency_rules: AdjacencyRules<D>,
+        tile_frequencies: Vec<Weight>,
+        dims: D::Loc,
+        rng: impl RngCore + Send + 'static,
+    ) -> Self {
+        let adjacency_table =
+            AdjacencyTable::with_num_tiles(&adjacency_rules, tile_frequencies.len());
+        return Self::from_table(adjacency_table, tile_frequencies, dims, WfcRng::new(rng));
+    }
 
-    use super::*;
+    fn from_table(
+        adjacency_table: AdjacencyTable<D>,
+        tile_frequencies: Vec<Weight>,
+        dims: D::Loc,
+        mut rng: WfcRng,
+    ) -> Self {
+        let num_cells = D::num_cells(dims);
 
-    fn all_adjacency_rules_satisfied(model: &Model) {
-        for cell_loc in model.board.grid.iter_locs() {
-            let cell = model.get_cell(cell_loc).unwrap();
-            for (dir, adjacent_cell_loc) in model.board.cardinal_neighbors(cell_loc) {
-                // if adjacent_cell_loc inbounds (adj_cell exists)
-                if let Some(adj_cell) = model.board.get_cell(adjacent_cell_loc) {
-                    let mut cell_domain_in_dir: Vec<usize> = cell
-                        .domain
-                        .allowed_tile_ids()
-                        .flat_map(|tile_id| model.adjacency_rules.enabled_by(tile_id, dir))
-                        .collect();
-                    cell_domain_in_dir.sort();
-                    cell_domain_in_dir.dedup();
-                    for adj_allowed_tile_id in adj_cell.domain.allowed_tile_ids() {
-                        assert!(cell_domain_in_dir.contains(&adj_allowed_tile_id), "cell at {cell_loc:?} with domain {:?} in direction {dir:?} has neighbor at {adjacent_cell_loc} with possible tile {} that should not be allowed", cell_domain_in_dir.iter().map(|&tile_id| CHARS[tile_id]).collect::<Vec<&str>>(), CHARS[adj_allowed_tile_id]);
-                    }
-                }
-            }
+        // TODO: consider just initializing these in  cell init
+        // for cleanliness
+        let weights = Weights {
+            frequencies: tile_frequencies,
+            tile_weights: Arc::new(Frequencies),
+        };
+        let mut entropy_heap = MinEntropyHeap::new(num_cells);
+        let num_tiles = adjacency_table.len();
+
+        let mut cells = Vec::with_capacity(num_cells);
+
+        for i in 0..num_cells {
+            let loc = D::loc(i, dims);
+            let random_entropy = rng.gen();
+            let probability_dict =
+                ProbabilityDict::new((0..num_tiles).map(|id| weights.get(loc, id)));
+            let cell = Cell::new(probability_dict, num_tiles, loc, random_entropy);
+            entropy_heap.push(cell.get_entropy_entry(&MinEntropy, dims, i));
+            cells.push(cell);
         }
+        let board = Board::new(dims, cells, &adjacency_table);
+        let updated_cells = Vec::with_capacity(num_cells);
+        let mut model = Self {
+            adjacency_table,
+            updated_cells,
+            board,
+            weights,
+            // dims,
+            entropy_heap,
+            heuristic: Arc::new(MinEntropy),
+            remaining_uncollapsed: num_cells as u32,
+            wave: Vec::new(),
+            rng,
+            contradiction: None,
+            backtracking: None,
+            decisions: VecDeque::new(),
+            backtracks: 0,
+            constraints: Vec::new(),
+            count_limits: Vec::new(),
+            connectivity: None,
+            unchecked_collapses: 0,
+        };
+        model.ban_unsupported();
+        return model;
     }
 
-    #[test]
-    fn adjacency_rules_fulfilled_always() {
-        let mut wfc = construct_simple_patterns().with_output_dimensions(40, 40);
-        let mut model = wfc.get_model();
-        while model.remaining_uncollapsed > 0 {
-            if model.wave.is_empty() {
-                all_adjacency_rules_satisfied(&model);
+    /// Bans the tiles that have no enablers in a direction the cell has a neighbor in,
+    /// they can never be placed there without breaking the adjacency rules.
+    ///
+    /// The removals are added to the wave and propogated by the following steps
+    fn ban_unsupported(&mut self) {
+        let table = &self.adjacency_table;
+        let unsupported: D::Array<Vec<TileId>> = D::array_from_fn(|dir| {
+            (0..table.len())
+                .filter(|&id| table.enabled_by(id, dir).is_empty())
+                .collect()
+        });
+        if unsupported.as_ref().iter().all(Vec::is_empty) {
+            return;
+        }
+        for i in 0..self.board.len() {
+            let mut banned = false;
+            for dir in D::all() {
+                if self.board.neighbor(i, dir).is_none() {
+                    continue;
+                }
+                for &tile in &unsupported[dir] {
+                    match self.board.ban(i, tile, &self.weights) {
+                        Ok(events) => {
+                            banned |= !events.is_empty();
+                            self.wave.extend(events);
+                        }
+                        Err(contradiction) => {
+                            self.contradiction = Some(contradiction);
+                            return;
+                        }
+                    }
+                }
+            }
+            if banned {
+                self.update_entropy_entry(i);
             }
-            model.step();
         }
     }
 
-    #[test]
-    fn entropy_calculations() {
-        let tile_frequencies = [1, 2, 3, 4, 5];
-        let calculated_entropy: f32 = 2.149;
-        let mut prob = ProbabilityDict::new(&tile_frequencies.to_vec());
-        assert!((calculated_entropy - prob.entropy()) <= f32::EPSILON);
-        prob.remove(4);
-        assert!(prob.total_count == 10);
-        let calculated_entropy: f32 = 1.621;
-        assert!((calculated_entropy - prob.entropy()) <= f32::EPSILON);
+    /// Overrides the weight of the given tiles
+    ///
+    /// The weights are used both when choosing which tile a cell collapses to and
+    /// in the entropy of each cell. Tiles that are not overridden keep the
+    /// frequency passed to the constructor
+    pub fn with_weight_overrides(
+        mut self,
+        overrides: impl IntoIterator<Item = (TileId, Weight)>,
+    ) -> Self {
+        self.set_weights(overrides);
+        return self;
     }
 
-    #[test]
-    fn entropy_heap_is_min_heap() {
-        let mut heap = MinEntropyHeap::new();
+    /// Changes the weight of the given tiles, see [`Model::with_weight_overrides`]
+    ///
+    /// Can be called in the middle of a solve, e.g. to boost a tile over time.
+    /// Only the cells that are not collapsed yet are affected
+    pub fn set_weights(&mut self, overrides: impl IntoIterator<Item = (TileId, Weight)>) {
+        for (id, weight) in overrides {
+            debug_assert!(
+                weight.is_finite() && weight >= 0.0,
+                "invalid weight {weight}"
+            );
+            self.weights.frequencies[id] = weight;
+        }
+        self.board.reset_probabilities(&self.weights);
+        // entropies have changed so the existing entries are invalid
+        self.rebuild_entropy_heap();
+    }
+
+    /// Sets how the weight of each tile is decided per cell, see [`crate::weights`].
+    /// Defaults to [`Frequencies`]
+    ///
+    /// Should be called before any tiles are collapsed
+    pub fn with_tile_weights(mut self, tile_weights: impl TileWeights<D::Loc> + 'static) -> Self {
+        self.weights.tile_weights = Arc::new(tile_weights);
+        self.board.reset_probabilities(&self.weights);
+        self.rebuild_entropy_heap();
+        return self;
+    }
+
+    /// Sets the heuristic that picks which cell is collapsed next, see [`crate::heuristic`].
+    /// Defaults to [`MinEntropy`]
+    pub fn with_heuristic(mut self, heuristic: impl CellHeuristic<D::Loc> + 'static) -> Self {
+        self.heuristic = Arc::new(heuristic);
+        self.rebuild_entropy_heap();
+        return self;
+    }
+
+    fn rebuild_entropy_heap(&mut self) {
+        self.entropy_heap.clear();
+        for i in 0..self.board.len() {
+            self.update_entropy_entry(i);
+        }
+    }
+
+    /// Recomputes the priority of the cell at index `i`, removing it from the heap
+    /// once it is collapsed
+    fn update_entropy_entry(&mut self, i: usize) {
+        let cell = &self.board.vals[i];
+        if cell.collapsed() {
+            self.entropy_heap.remove(i);
+            return;
+        }
+        let entry = cell.get_entropy_entry(&*self.heuristic, self.board.dims, i);
+        self.entropy_heap.push(entry);
+    }
+
+    /// Enables backtracking. Instead of failing on a contradiction the model
+    /// undoes the most recent collapse and bans the tile that was chosen
+    pub fn with_backtracking(mut self, backtracking: Backtracking) -> Self {
+        self.backtracking = Some(backtracking);
+        return self;
+    }
+
+    /// Makes the output wrap around on the given axes so that it tiles seamlessly,
+    /// i.e. cells on opposite edges are treated as neighbors
+    ///
+    /// Can be called after tiles have been set, the cells on the edges are constrained by
+    /// the cells they now wrap around to
+    pub fn with_periodic(mut self, periodic: D::Axes) -> Self {
+        if self.contradiction.is_some() {
+            // reset rebuilds the board with the new neighbors anyway
+            self.board.periodic = periodic;
+            return self;
+        }
+        if let Err(contradiction) = self.rewrap(periodic) {
+            log::warn!("wrapping the edges failed: {contradiction}");
+            self.contradiction = Some(contradiction);
+        }
+        return self;
+    }
+
+    /// Recounts the supports of the cells on the edges for their new neighbors and bans
+    /// the tiles that lost all of their support in some direction
+    fn rewrap(&mut self, periodic: D::Axes) -> Result<(), WfcError<D::Loc>> {
+        // the supports are recounted from the domains of the neighbors, removals that
+        // haven't been propogated yet would be counted twice
+        self.propogate_all()?;
+        // the recounted supports aren't on the trail
+        self.clear_decisions();
+        let mut edges = self.board.edge_cells();
+        self.board.periodic = periodic;
+        edges.extend(self.board.edge_cells());
+        edges.sort_unstable();
+        edges.dedup();
+        for &i in &edges {
+            self.board.recompute_supports(i, &self.adjacency_table);
+        }
+        for &i in &edges {
+            let unsupported: Vec<TileId> = self
+                .board
+                .domain(i)
+                .iter()
+                .filter(|&tile| {
+                    D::all().into_iter().any(|dir| {
+                        self.board.neighbor(i, dir).is_some()
+                            && self.board.supports[dir][i * self.board.num_tiles + tile] == 0
+                    })
+                })
+                .collect();
+            if unsupported.is_empty() {
+                continue;
+            }
+            for tile in unsupported {
+                let events = self.board.ban(i, tile, &self.weights)?;
+                self.wave.extend(events);
+            }
+            self.update_entropy_entry(i);
+        }
+        return self.propogate_all();
+    }
+
+    /// Collapses the cell at `loc` to `tile` and propogates the removals immediately
+    ///
+    /// The constraint is kept when the model is reset (i.e. between [`Model::solve`]
+    /// attempts). Returns a Contradiction if the board can no longer be completed,
+    /// panics if `loc` is out of bounds
+    pub fn set_tile(&mut self, loc: D::Loc, tile: TileId) -> Result<(), WfcError<D::Loc>> {
+        return self.apply_constraints(vec![CellConstraint::Set { loc, tile }]);
+    }
+
+    /// Removes `tile` from the possible tiles of the cell at `loc` and propogates the
+    /// removal immediately. See [`Model::set_tile`]
+    pub fn ban(&mut self, loc: D::Loc, tile: TileId) -> Result<(), WfcError<D::Loc>> {
+        return self.apply_constraints(vec![CellConstraint::Ban { loc, tile }]);
+    }
+
+    /// Only allows the given tiles in each of the cells, removing every other tile from them
+    ///
+    /// The removals of all the cells are propogated together, which is much faster than
+    /// banning the tiles one at a time. See [`Model::set_tile`]
+    pub fn restrict_cells<T>(
+        &mut self,
+        cells: impl IntoIterator<Item = (D::Loc, T)>,
+    ) -> Result<(), WfcError<D::Loc>>
+    where
+        T: IntoIterator<Item = TileId>,
+    {
+        let constraints = cells
+            .into_iter()
+            .map(|(loc, allowed)| CellConstraint::Restrict {
+                loc,
+                allowed: self.tile_mask(allowed),
+            })
+            .collect();
+        return self.apply_constraints(constraints);
+    }
+
+    /// A set of tiles stored like the domain of a cell
+    fn tile_mask(&self, tiles: impl IntoIterator<Item = TileId>) -> Box<[Word]> {
+        let mut mask = vec![0; self.board.words_per_cell].into_boxed_slice();
+        let mut set = TileSetMut::new(&mut mask);
+        for tile in tiles {
+            set.insert(tile);
+        }
+        return mask;
+    }
+
+    /// Keeps the constraints and applies them together, propogating the removals once
+    fn apply_constraints(
+        &mut self,
+        constraints: Vec<CellConstraint<D::Loc>>,
+    ) -> Result<(), WfcError<D::Loc>> {
+        if let Some(contradiction) = self.contradiction {
+            return Err(contradiction);
+        }
+        // decisions made before the constraints can't be undone without losing them
+        self.clear_decisions();
+        let result = constraints
+            .iter()
+            .try_for_each(|constraint| self.constrain_cell(constraint))
+            .and_then(|()| self.propogate_all());
+        self.constraints.extend(constraints);
+        if let Err(contradiction) = result {
+            log::warn!("constraints failed: {contradiction}");
+            self.contradiction = Some(contradiction);
+        }
+        return result;
+    }
+
+    fn constrain_cell(
+        &mut self,
+        constraint: &CellConstraint<D::Loc>,
+    ) -> Result<(), WfcError<D::Loc>> {
+        let loc = constraint.loc();
+        assert!(
+            self.board.inbounds(loc),
+            "constrained cell at {loc} is out of bounds"
+        );
+        if let CellConstraint::Set { tile, .. } | CellConstraint::Ban { tile, .. } = *constraint {
+            if tile >= self.adjacency_table.len() {
+                return Err(WfcError::UnknownTile { loc, tile });
+            }
+        }
+        let i = self.board.index(loc);
+        let weights = &self.weights;
+        self.updated_cells.push(loc);
+        let events = match *constraint {
+            CellConstraint::Set { tile, .. } => match self.board.vals[i].collapsed_to {
+                Some(collapsed_to) if collapsed_to == tile => Vec::new(),
+                Some(_) => return Err(WfcError::Contradiction { loc }),
+                None => {
+                    self.remaining_uncollapsed -= 1;
+                    self.board.collapse_to(i, tile, weights)?
+                }
+            },
+            CellConstraint::Ban { tile, .. } => self.board.ban(i, tile, weights)?,
+            CellConstraint::Restrict { ref allowed, .. } => {
+                self.board.restrict(i, TileSet::new(allowed), weights)?
+            }
+        };
+        self.update_entropy_entry(i);
+        self.wave.extend(events);
+        return Ok(());
+    }
+
+    fn propogate_all(&mut self) -> Result<(), WfcError<D::Loc>> {
+        loop {
+            while !self.wave.is_empty() {
+                self.propogate()?;
+            }
+            self.enforce_global_constraints()?;
+            if self.wave.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Limits the number of cells `tile` is placed in, a cell counts once `tile` is the
+    /// only one left in it. Replaces the previous limits of `tile`
+    ///
+    /// Once `max` cells are placed the tile is banned from every other cell, and once
+    /// only `min` cells can still hold it they are all set to it. The limits are kept
+    /// when the model is reset
+    pub fn with_tile_count(mut self, tile: TileId, count: TileCount) -> Self {
+        self.count_limits.retain(|&(limited, _)| limited != tile);
+        self.count_limits.push((tile, count));
+        if let Err(contradiction) = self.enforce_global_constraints() {
+            // returned by the next step
+            self.contradiction = Some(contradiction);
+        }
+        return self;
+    }
+
+    /// Keeps the walkable tiles of `connectivity` in a single connected region
+    ///
+    /// Walkable cells that can no longer reach the cells that must be walkable lose their
+    /// walkable tiles. Cutting the walkable cells in two is reported as a contradiction,
+    /// use [`Model::with_backtracking`] or [`Model::solve`] to recover from it
+    pub fn with_connectivity(mut self, connectivity: Connectivity<D>) -> Self {
+        self.connectivity = Some(connectivity);
+        if let Err(contradiction) = self.enforce_global_constraints() {
+            // returned by the next step
+            self.contradiction = Some(contradiction);
+        }
+        return self;
+    }
+
+    /// Enforces the constraints on the whole board, i.e. the tile counts and connectivity
+    fn enforce_global_constraints(&mut self) -> Result<(), WfcError<D::Loc>> {
+        self.enforce_tile_counts()?;
+        self.enforce_connectivity()?;
+        return Ok(());
+    }
+
+    /// Whether enough cells have been collapsed since the last check of the connectivity,
+    /// see [`Connectivity::with_check_interval`]
+    fn connectivity_check_due(&self) -> bool {
+        let Some(connectivity) = &self.connectivity else {
+            return false;
+        };
+        let interval = connectivity
+            .check_interval
+            .unwrap_or((self.board.len() as u32 / 64).max(1));
+        return self.unchecked_collapses >= interval || self.remaining_uncollapsed == 0;
+    }
+
+    /// Finds the regions of cells that could be connected and bans the walkable tiles
+    /// from the regions without any cells that must be walkable
+    ///
+    /// Returns a contradiction if the cells that must be walkable are in different regions
+    fn enforce_connectivity(&mut self) -> Result<(), WfcError<D::Loc>> {
+        self.unchecked_collapses = 0;
+        let Some(connectivity) = &self.connectivity else {
+            return Ok(());
+        };
+        let num_cells = self.board.len();
+        // the sides each cell could connect through, `None` if it can't be walkable
+        let mut exits: Vec<Option<D::Array<bool>>> = (0..num_cells).map(|_| None).collect();
+        let mut must_walk = vec![false; num_cells];
+        for i in 0..num_cells {
+            let mut all_walkable = true;
+            for tile in self.board.domain(i).iter() {
+                match connectivity.exits(tile) {
+                    Some(tile_exits) => {
+                        let cell_exits =
+                            exits[i].get_or_insert_with(|| D::array_from_fn(|_| false));
+                        for side in D::all() {
+                            cell_exits[side] |= tile_exits[side];
+                        }
+                    }
+                    None => all_walkable = false,
+                }
+            }
+            must_walk[i] = all_walkable && exits[i].is_some();
+        }
+
+        const UNVISITED: usize = usize::MAX;
+        let mut regions = vec![UNVISITED; num_cells];
+        // the region that holds the cells that must be walkable
+        let mut walked_region = None;
+        let mut stack = Vec::new();
+        for start in 0..num_cells {
+            if exits[start].is_none() || regions[start] != UNVISITED {
+                continue;
+            }
+            let mut must_walk_in_region = None;
+            regions[start] = start;
+            stack.push(start);
+            while let Some(i) = stack.pop() {
+                if must_walk[i] {
+                    must_walk_in_region = Some(i);
+                }
+                let cell_exits = exits[i].as_ref().unwrap();
+                for dir in D::all() {
+                    if !cell_exits[dir] {
+                        continue;
+                    }
+                    let Some(j) = self.board.neighbor(i, dir) else {
+                        continue;
+                    };
+                    let connects = exits[j]
+                        .as_ref()
+                        .is_some_and(|neighbor_exits| neighbor_exits[-dir]);
+                    if connects && regions[j] == UNVISITED {
+                        regions[j] = start;
+                        stack.push(j);
+                    }
+                }
+            }
+            if let Some(i) = must_walk_in_region {
+                if walked_region.is_some() {
+                    return Err(WfcError::Disconnected {
+                        loc: self.board.vals[i].loc,
+                    });
+                }
+                walked_region = Some(start);
+            }
+        }
+
+        let Some(walked_region) = walked_region else {
+            return Ok(());
+        };
+        // the walkable tiles of the cells that could be walkable but are cut off
+        let cut_off: Vec<(usize, Vec<TileId>)> = (0..num_cells)
+            .filter(|&i| exits[i].is_some() && regions[i] != walked_region)
+            .map(|i| {
+                let domain = self.board.domain(i);
+                (
+                    i,
+                    domain
+                        .iter()
+                        .filter(|&tile| connectivity.exits(tile).is_some())
+                        .collect(),
+                )
+            })
+            .collect();
+        for (i, walkable) in cut_off {
+            log::trace!("cell {i} is cut off, banning {walkable:?}");
+            for tile in walkable {
+                let events = self.board.ban(i, tile, &self.weights)?;
+                self.wave.extend(events);
+            }
+            self.updated_cells.push(self.board.vals[i].loc);
+            self.update_entropy_entry(i);
+        }
+        return Ok(());
+    }
+
+    /// Bans or sets the tiles that have reached one of their count limits. The removals
+    /// are added to the wave
+    ///
+    /// Returns a contradiction if a limit can no longer be met
+    fn enforce_tile_counts(&mut self) -> Result<(), WfcError<D::Loc>> {
+        for k in 0..self.count_limits.len() {
+            let (tile, count) = self.count_limits[k];
+            let placed = self.board.tile_counts.placed[tile];
+            let possible = self.board.tile_counts.possible[tile];
+            if placed > count.max || possible < count.min {
+                return Err(WfcError::TileCount { tile });
+            }
+            let ban = placed == count.max && possible > placed;
+            let force = possible == count.min && placed < count.min;
+            if !ban && !force {
+                continue;
+            }
+            log::info!(
+                "tile {tile} reached its count limit, placed: {placed} possible: {possible}"
+            );
+            for i in 0..self.board.len() {
+                let cell = &self.board.vals[i];
+                if cell.is_decided() || !self.board.domain(i).contains(tile) {
+                    continue;
+                }
+                let loc = cell.loc;
+                let events = if ban {
+                    self.board.ban(i, tile, &self.weights)?
+                } else {
+                    self.remaining_uncollapsed -= 1;
+                    self.board.collapse_to(i, tile, &self.weights)?
+                };
+                self.wave.extend(events);
+                self.updated_cells.push(loc);
+                self.update_entropy_entry(i);
+            }
+        }
+        return Ok(());
+    }
+
+    /// The dimensions of the output in cells
+    pub fn dims(&self) -> D::Loc {
+        return self.board.dims;
+    }
+
+    pub fn tile_frequencies(&self) -> &IdMap<Weight> {
+        return &self.weights.frequencies;
+    }
+
+    pub fn get_cell(&self, loc: D::Loc) -> Option<CellView<'_, D::Loc>> {
+        if !self.board.inbounds(loc) {
+            return None;
+        }
+        return Some(self.board.view(self.board.index(loc), &self.weights));
+    }
+
+    /// Pops the uncollapsed cell with the lowest priority. `None` once every cell is collapsed
+    pub fn get_cell_to_collapse(&mut self) -> Option<D::Loc> {
+        if self.remaining_uncollapsed == 0 {
+            return None;
+        }
+        while let Some(entry) = self.entropy_heap.pop() {
+            let cell = &self.board.vals[entry.index];
+            if !cell.collapsed() {
+                return Some(cell.loc);
+            }
+        }
+        return None;
+    }
+
+    pub fn collapse_cell(&mut self) -> Result<(), WfcError<D::Loc>> {
+        let Some(loc) = self.get_cell_to_collapse() else {
+            // every uncollapsed cell should be in the heap, report the first one instead of
+            // never finishing
+            if let Some(cell) = self.board.vals.iter().find(|cell| !cell.collapsed()) {
+                return Err(WfcError::Contradiction { loc: cell.loc });
+            }
+            return Ok(());
+        };
+        log::info!("Collapsing Cell at {loc:?}");
+        let i = self.board.index(loc);
+        let weights = &self.weights;
+        let tile_removed_events = match self.backtracking {
+            Some(backtracking) => {
+                let tile_id = self
+                    .board
+                    .choose_collapse_tile(i, weights, &mut self.rng)
+                    .ok_or(WfcError::Contradiction { loc })?;
+                self.push_decision(i, tile_id, backtracking.max_depth);
+                self.board.collapse_to(i, tile_id, &self.weights)?
+            }
+            None => self.board.collapse(i, weights, &mut self.rng)?,
+        };
+
+        self.wave = tile_removed_events;
+        self.remaining_uncollapsed -= 1;
+        self.unchecked_collapses += 1;
+        self.updated_cells.push(loc);
+        log::trace!(
+            "Collapsed cell {:?}. Removed {}/{} tile options",
+            loc,
+            self.wave.len(),
+            self.adjacency_table.len()
+        );
+        return Ok(());
+    }
+
+    pub fn propogate(&mut self) -> Result<(), WfcError<D::Loc>> {
+        match self.wave.pop() {
+            Some(event) => {
+                log::info!(
+                    "Propogating removal of tile {} from cell at {:?}",
+                    event.tile_id,
+                    event.cell_loc
+                );
+                let from = self.board.index(event.cell_loc);
+                for dir in D::all() {
+                    let Some(i) = self.board.neighbor(from, dir) else {
+                        continue;
+                    };
+                    let adjacent_tile_loc = self.board.vals[i].loc;
+                    log::trace!(
+                        "{:?} -> {:?} -> {:?}",
+                        event.cell_loc,
+                        dir,
+                        adjacent_tile_loc
+                    );
+                    let removal = self.board.remove_enabler(
+                        i,
+                        event.tile_id,
+                        dir,
+                        &self.adjacency_table,
+                        &self.weights,
+                    );
+                    if removal.is_err() {
+                        self.updated_cells.push(adjacent_tile_loc);
+                    }
+                    if let Some(tile_removed_events) = removal? {
+                        log::trace!("removed {} options", tile_removed_events.len());
+                        for event in tile_removed_events {
+                            self.wave.push(event);
+                        }
+                        self.updated_cells.push(adjacent_tile_loc);
+                        self.update_entropy_entry(i);
+                    }
+                }
+            }
+            None => unreachable!("If wave was empty we should have collapsed a cell instead"),
+        }
+        return Ok(());
+    }
+
+    fn push_decision(&mut self, i: usize, tile_id: TileId, max_depth: usize) {
+        if max_depth == 0 {
+            return;
+        }
+        if self.decisions.is_empty() {
+            // changes made before the first decision are never undone
+            self.board.trail.clear();
+            self.board.recording = true;
+        }
+        if self.decisions.len() >= max_depth {
+            self.decisions.pop_front();
+            let forgotten = self
+                .decisions
+                .front()
+                .map_or(self.board.trail.len(), |d| d.trail_start);
+            self.board.trail.drain(..forgotten);
+            for decision in self.decisions.iter_mut() {
+                decision.trail_start -= forgotten;
+            }
+        }
+        self.decisions.push_back(Decision {
+            trail_start: self.board.trail.len(),
+            remaining_uncollapsed: self.remaining_uncollapsed,
+            unchecked_collapses: self.unchecked_collapses,
+            i,
+            tile_id,
+        });
+    }
+
+    fn clear_decisions(&mut self) {
+        self.decisions.clear();
+        self.board.trail.clear();
+        self.board.recording = false;
+    }
+
+    /// Undoes the most recent collapse and bans the tile that was chosen.
+    /// Returns false if there is nothing left to undo
+    fn backtrack(&mut self, backtracking: Backtracking) -> bool {
+        while let Some(decision) = self.decisions.pop_back() {
+            if self.backtracks >= backtracking.max_backtracks {
+                log::warn!("backtrack limit of {} reached", backtracking.max_backtracks);
+                return false;
+            }
+            self.backtracks += 1;
+            log::info!(
+                "Backtracking: banning tile {} from cell at {:?}",
+                decision.tile_id,
+                self.board.vals[decision.i].loc
+            );
+            let mut restored = self.board.undo(decision.trail_start);
+            restored.sort_unstable();
+            restored.dedup();
+            self.updated_cells.clear();
+            for &i in &restored {
+                self.board.recompute_probability(i, &self.weights);
+                self.update_entropy_entry(i);
+                self.updated_cells.push(self.board.vals[i].loc);
+            }
+            self.remaining_uncollapsed = decision.remaining_uncollapsed;
+            self.unchecked_collapses = decision.unchecked_collapses;
+            self.wave.clear();
+
+            let i = decision.i;
+            match self.board.ban(i, decision.tile_id, &self.weights) {
+                Ok(events) => {
+                    self.update_entropy_entry(i);
+                    self.wave = events;
+                    return true;
+                }
+                // every choice for this cell failed, undo the decision before it
+                Err(_) => continue,
+            }
+        }
+        return false;
+    }
+
+    /// Resets every cell to its initial state and reseeds the model
+    ///
+    /// Keeps the adjacency rules, weights, settings and constraints, as well as all allocations.
+    /// The result is identical to creating a new model with [`Model::from_seed`]
+    /// and applying the same constraints
+    pub fn reset(&mut self, seed: u64) {
+        self.rng = WfcRng::from_seed(seed);
+        for cell in self.board.vals.iter_mut() {
+            cell.collapsed_to = None;
+            cell.random_entropy = self.rng.gen();
+        }
+        self.board.reset_domains(&self.adjacency_table);
+        self.board.reset_probabilities(&self.weights);
+        self.rebuild_entropy_heap();
+        self.remaining_uncollapsed = self.board.len() as u32;
+        self.wave.clear();
+        self.updated_cells.clear();
+        self.contradiction = None;
+        self.clear_decisions();
+        self.backtracks = 0;
+        self.unchecked_collapses = 0;
+        self.ban_unsupported();
+        if self.contradiction.is_some() {
+            return;
+        }
+        // the constraints are applied together and propogated once
+        let constraints = std::mem::take(&mut self.constraints);
+        let result = constraints
+            .iter()
+            .try_for_each(|constraint| self.constrain_cell(constraint))
+            .and_then(|()| self.propogate_all());
+        self.constraints = constraints;
+        if let Err(contradiction) = result {
+            log::warn!("constraints failed on reset: {contradiction}");
+            self.contradiction = Some(contradiction);
+        }
+    }
+
+    /// Runs the model to completion, restarting with a new seed derived from `seed`
+    /// whenever it hits a contradiction, up to `max_attempts` times
+    ///
+    /// The first attempt uses `seed` itself
+    pub fn solve(&mut self, seed: u64, max_attempts: u32) -> Result<SolveReport, WfcError<D::Loc>> {
+        for attempt in 0..max_attempts {
+            let attempt_seed = derive_seed(seed, attempt);
+            self.reset(attempt_seed);
+            match self.run() {
+                Ok(()) => {
+                    return Ok(SolveReport {
+                        attempts: attempt + 1,
+                        seed: attempt_seed,
+                    });
+                }
+                Err(err) => log::info!("attempt {} failed: {err}", attempt + 1),
+            }
+        }
+        return Err(WfcError::AttemptsExhausted {
+            attempts: max_attempts,
+        });
+    }
+
+    /// Whether every cell has been collapsed and all of the resulting removals propogated
+    pub fn is_done(&self) -> bool {
+        return self.remaining_uncollapsed == 0 && self.wave.is_empty();
+    }
+
+    /// steps the model one iteration by either collapsing or propogating TileRemovalEvents
+    ///
+    /// Returns the cells that were updated, or the Contradiction that was found.
+    /// Once a step has failed every following step returns the same error
+    pub fn step(&mut self) -> Result<Vec<D::Loc>, WfcError<D::Loc>> {
+        if let Some(contradiction) = self.contradiction {
+            return Err(contradiction);
+        }
+        // no tiles left to collapse or propogate -> done
+        if self.is_done() {
+            for cell in self.board.iter() {
+                assert!(cell.is_decided());
+            }
+            return Ok(Vec::new());
+        }
+        // stack empty -> need to collapse a tile
+        let mut result = if self.wave.is_empty() {
+            self.collapse_cell()
+        } else {
+            self.propogate()
+        };
+        if result.is_ok() && self.wave.is_empty() {
+            result = self.enforce_tile_counts();
+        }
+        // the connectivity is checked across the whole board so only every so often
+        if result.is_ok() && self.wave.is_empty() && self.connectivity_check_due() {
+            result = self.enforce_connectivity();
+        }
+        if let Err(contradiction) = result {
+            if let Some(backtracking) = self.backtracking {
+                if self.backtrack(backtracking) {
+                    return Ok(std::mem::take(&mut self.updated_cells));
+                }
+            }
+            log::warn!("{contradiction}");
+            self.contradiction = Some(contradiction);
+            return Err(contradiction);
+        }
+
+        let mut updated_cells: Vec<D::Loc> = Vec::with_capacity(self.updated_cells.len());
+        // NOTE: append moves the values out of self.updated_cells and into the new vec that is
+        // returned
+        updated_cells.append(&mut self.updated_cells);
+        return Ok(updated_cells);
+    }
+
+    /// Steps the model until every cell is collapsed
+    pub fn run(&mut self) -> Result<(), WfcError<D::Loc>> {
+        while !self.is_done() {
+            self.step()?;
+        }
+        return Ok(());
+    }
+
+    pub fn iter_cells(&self) -> impl Iterator<Item = CellView<'_, D::Loc>> {
+        return (0..self.board.len()).map(|i| self.board.view(i, &self.weights));
+    }
+}
+
+impl Model {
+    /// Creates a model with some of the cells already set, e.g. a partially drawn map to
+    /// complete
+    ///
+    /// `tiles` holds the tile of every cell in row major order, `None` for the cells to fill
+    /// in. The fixed cells are kept as constraints, see [`Model::set_tile`], and propogated
+    /// before the model is returned. Returns every pair of neighboring fixed cells that
+    /// conflict, or the contradiction propogating them led to
+    pub fn from_grid(
+        adjacency_rules: AdjacencyRules,
+        tile_frequencies: Vec<Weight>,
+        dims: UVec2,
+        tiles: Vec<Option<TileId>>,
+        seed: u64,
+    ) -> Result<Self, GridError> {
+        let grid = Grid(dims);
+        assert_eq!(
+            tiles.len(),
+            grid.area() as usize,
+            "a tile is needed for every cell"
+        );
+        let tile_at = |loc: UVec2| tiles[(loc.y * dims.x + loc.x) as usize];
+        // checked before the conflicts, the rules don't allow unknown tiles next to anything
+        let unknown = grid.iter_locs().find_map(|loc| {
+            tile_at(loc)
+                .filter(|&tile| tile >= tile_frequencies.len())
+                .map(|tile| WfcError::UnknownTile { loc, tile })
+        });
+        if let Some(unknown) = unknown {
+            return Err(GridError::Unsatisfiable(unknown));
+        }
+        let mut conflicts = Vec::new();
+        for a in grid.iter_locs() {
+            let Some(a_tile) = tile_at(a) else {
+                continue;
+            };
+            for dir in [CardinalDirs::Right, CardinalDirs::Down] {
+                let b = dir + a.as_ivec2();
+                if b.cmpge(dims.as_ivec2()).any() {
+                    continue;
+                }
+                let b = b.as_uvec2();
+                let Some(b_tile) = tile_at(b) else {
+                    continue;
+                };
+                if !adjacency_rules.is_allowed(a_tile, b_tile, dir) {
+                    conflicts.push(Conflict { a, b });
+                }
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(GridError::Conflicts(conflicts));
+        }
+
+        let mut model = Self::from_seed(adjacency_rules, tile_frequencies, dims, seed);
+        let constraints = grid
+            .iter_locs()
+            .filter_map(|loc| tile_at(loc).map(|tile| CellConstraint::Set { loc, tile }))
+            .collect();
+        model
+            .apply_constraints(constraints)
+            .map_err(GridError::Unsatisfiable)?;
+        return Ok(model);
+    }
+
+    /// Bans every tile the border doesn't allow from the cells along each side of
+    /// the output. The bans are kept as constraints, see [`Model::restrict_cells`]
+    ///
+    /// The sides on periodic axes wrap around instead of having a border, so call
+    /// [`Model::with_periodic`] first
+    pub fn with_border(mut self, border: Border) -> Result<Self, WfcError> {
+        let num_tiles = self.adjacency_table.len();
+        let mut cells = Vec::new();
+        for loc in self.board.grid().iter_locs() {
+            let i = self.board.index(loc);
+            let sides: Vec<&Vec<TileId>> = CardinalDirs::as_array()
+                .into_iter()
+                .filter(|&side| self.board.neighbor(i, side).is_none())
+                .filter_map(|side| border.allowed[side].as_ref())
+                .collect();
+            if sides.is_empty() {
+                continue;
+            }
+            let allowed =
+                (0..num_tiles).filter(|tile| sides.iter().all(|side| side.contains(tile)));
+            cells.push((loc, allowed.collect::<Vec<_>>()));
+        }
+        self.restrict_cells(cells)?;
+        return Ok(self);
+    }
+
+    /// Only allows the tiles the rules allow next to the tiles around the output in the cells
+    /// along its edges, e.g. to solve part of a larger output. `outside` gives the tile at a
+    /// location outside the output, or None if there isn't one there.
+    /// The constraints are kept when the model is reset
+    pub fn constrain_to_surroundings(
+        &mut self,
+        outside: impl Fn(IVec2) -> Option<TileId>,
+    ) -> Result<(), WfcError> {
+        let num_tiles = self.adjacency_table.len();
+        let mut cells = Vec::new();
+        for loc in self.board.grid().iter_locs() {
+            let neighbors: Vec<(CardinalDirs, TileId)> = CardinalDirs::as_array()
+                .into_iter()
+                .filter_map(|side| {
+                    let outside_loc = self.board.wrap(side + loc.as_ivec2());
+                    if self.board.contains(outside_loc) {
+                        return None;
+                    }
+                    return outside(outside_loc).map(|tile| (side, tile));
+                })
+                .collect();
+            if neighbors.is_empty() {
+                continue;
+            }
+            let table = &self.adjacency_table;
+            let allowed = (0..num_tiles).filter(|&tile| {
+                neighbors
+                    .iter()
+                    .all(|&(side, outside_tile)| table.is_allowed(outside_tile, tile, -side))
+            });
+            cells.push((loc, allowed.collect::<Vec<_>>()));
+        }
+        return self.restrict_cells(cells);
+    }
+
+    /// Constrains the model to the tiles around it with [`Model::constrain_to_surroundings`]
+    /// and solves it, returning the tile of every cell in row major order
+    pub(crate) fn solve_subregion(
+        &mut self,
+        outside: impl Fn(IVec2) -> Option<TileId>,
+        seed: u64,
+        max_attempts: u32,
+    ) -> Result<Vec<TileId>, WfcError> {
+        self.constrain_to_surroundings(outside)?;
+        self.solve(seed, max_attempts)?;
+        let tiles = self
+            .iter_cells()
+            .map(|cell| cell.collapsed_to.expect("solved models are collapsed"))
+            .collect();
+        return Ok(tiles);
+    }
+
+    /// Regenerates the cells from `min` up to but not including `max` of a finished model,
+    /// keeping every other cell as it is
+    ///
+    /// The region is solved on its own with a region sized model, against the cells around it
+    /// and with the settings and constraints of this model, so the cost only depends on the
+    /// size of the region. Restarts with a seed derived from `seed` whenever it hits a
+    /// contradiction, up to `max_attempts` times like [`Model::solve`]. If every attempt
+    /// fails the previous output is kept
+    pub fn reroll_region(
+        &mut self,
+        min: UVec2,
+        max: UVec2,
+        seed: u64,
+        max_attempts: u32,
+    ) -> Result<SolveReport, WfcError> {
+        assert!(
+            self.is_done() && self.contradiction.is_none(),
+            "only finished models can be rerolled"
+        );
+        assert!(
+            min.cmple(max).all() && max.cmple(self.dims()).all(),
+            "region {min} - {max} is out of bounds"
+        );
+        let size = max - min;
+        let previous: Vec<TileId> = Grid(size)
+            .iter_locs()
+            .map(|offset| self.board[(min + offset).as_ivec2()].collapsed_to.unwrap())
+            .collect();
+        let mut region = self.region_model(min, size, &previous, seed)?;
+        for attempt in 0..max_attempts {
+            let attempt_seed = derive_seed(seed, attempt);
+            region.reset(attempt_seed);
+            if let Err(err) = region.run() {
+                log::info!("reroll attempt {} failed: {err}", attempt + 1);
+                continue;
+            }
+            let tiles: Vec<TileId> = region
+                .iter_cells()
+                .map(|cell| cell.collapsed_to.unwrap())
+                .collect();
+            self.write_region(min, size, &tiles);
+            // the region can't see whether its walkable cells connect through the rest
+            if let Err(err) = self.enforce_connectivity() {
+                log::info!("reroll attempt {} failed: {err}", attempt + 1);
+                self.write_region(min, size, &previous);
+                continue;
+            }
+            return Ok(SolveReport {
+                attempts: attempt + 1,
+                seed: attempt_seed,
+            });
+        }
+        return Err(WfcError::AttemptsExhausted {
+            attempts: max_attempts,
+        });
+    }
+
+    /// A model of the cells from `min` to `min + size` with the settings and constraints of
+    /// this one, whose edge cells only allow the tiles the cells around the region allow.
+    /// `previous` holds the current tiles of the region in row major order
+    fn region_model(
+        &self,
+        min: UVec2,
+        size: UVec2,
+        previous: &[TileId],
+        seed: u64,
+    ) -> Result<Model, WfcError> {
+        let dims = self.dims();
+        let mut region = Model::from_table(
+            self.adjacency_table.clone(),
+            self.weights.frequencies.clone(),
+            size,
+            WfcRng::from_seed(seed),
+        )
+        .with_tile_weights(RegionWeights {
+            tile_weights: self.weights.tile_weights.clone(),
+            min,
+        })
+        .with_heuristic(RegionHeuristic {
+            heuristic: self.heuristic.clone(),
+            min,
+            dims,
+        });
+        region.backtracking = self.backtracking;
+        // a region spanning a periodic axis wraps around onto itself
+        let periodic = self.board.periodic & size.cmpeq(dims);
+        if periodic.any() {
+            region = region.with_periodic(periodic);
+        }
+        for &(tile, count) in &self.count_limits {
+            let inside = previous.iter().filter(|&&placed| placed == tile).count() as u32;
+            let outside = self.board.tile_counts.placed[tile] - inside;
+            let count = TileCount {
+                min: count.min.saturating_sub(outside),
+                max: count.max - outside,
+            };
+            region = region.with_tile_count(tile, count);
+        }
+        let constraints = self
+            .constraints
+            .iter()
+            .filter(|constraint| {
+                let loc = constraint.loc();
+                return loc.cmpge(min).all() && loc.cmplt(min + size).all();
+            })
+            .map(|constraint| constraint.moved_to(constraint.loc() - min))
+            .collect();
+        region.apply_constraints(constraints)?;
+        region.constrain_to_surroundings(|offset| {
+            let loc = self.board.wrap(min.as_ivec2() + offset);
+            if !self.board.contains(loc) {
+                return None;
+            }
+            return self.board[loc].collapsed_to;
+        })?;
+        return Ok(region);
+    }
+
+    /// Replaces the tiles of the collapsed cells from `min` to `min + size` with `tiles`, in
+    /// row major order
+    fn write_region(&mut self, min: UVec2, size: UVec2, tiles: &[TileId]) {
+        let mut changed = Vec::with_capacity(tiles.len() * 2);
+        for (offset, &tile) in Grid(size).iter_locs().zip(tiles) {
+            let loc = min + offset;
+            let i = self.board.index(loc);
+            self.board.replace_tile(i, tile, &self.weights);
+            self.updated_cells.push(loc);
+            changed.push(i);
+            // the cells around the region are supported by its tiles as well
+            changed.extend(
+                CardinalDirs::all()
+                    .into_iter()
+                    .filter_map(|dir| self.board.neighbor(i, dir)),
+            );
+        }
+        changed.sort_unstable();
+        changed.dedup();
+        for i in changed {
+            self.board.recompute_supports(i, &self.adjacency_table);
+        }
+    }
+}
+
+/// The number of enablers a tile has from one direction
+type Support = u16;
+
+/// The removals made by a change to the board
+type Events<D> = Vec<TileRemovalEvent<<D as Direction>::Loc>>;
+
+#[derive(Debug, Deref, DerefMut)]
+struct Board<D: Direction> {
+    dims: D::Loc,
+    #[deref_mut]
+    #[deref]
+    vals: Vec<Cell<D::Loc>>,
+    /// whether neighbors wrap around the edges on each axis
+    periodic: D::Axes,
+    num_tiles: usize,
+    /// the possible tiles of every cell, `words_per_cell` words each
+    domains: Vec<Word>,
+    words_per_cell: usize,
+    /// the number of enablers of every tile in every cell from each direction,
+    /// indexed by `cell * num_tiles + tile`
+    supports: D::Array<Vec<Support>>,
+    tile_counts: TileCounts,
+    /// the changes made since the oldest decision that can be undone
+    trail: VecDeque<TrailEntry<D>>,
+    recording: bool,
+}
+
+/// The number of cells each tile is placed in and can still be placed in
+#[derive(Debug, Clone, Default)]
+struct TileCounts {
+    /// the cells where the tile is the only one left
+    placed: IdMap<u32>,
+    /// the cells where the tile has not been removed, including the placed ones
+    possible: IdMap<u32>,
+}
+
+impl<D: Direction> Board<D> {
+    fn new(dims: D::Loc, cells: Vec<Cell<D::Loc>>, adjacency_table: &AdjacencyTable<D>) -> Self {
+        let num_tiles = adjacency_table.len();
+        assert!(
+            num_tiles <= Support::MAX as usize,
+            "at most {} tiles are supported",
+            Support::MAX
+        );
+        let mut board = Self {
+            dims,
+            vals: cells,
+            periodic: D::bounded(),
+            num_tiles,
+            domains: Vec::new(),
+            words_per_cell: TileSet::words_for(num_tiles),
+            supports: D::array_from_fn(|_| Vec::new()),
+            tile_counts: TileCounts::default(),
+            trail: VecDeque::new(),
+            recording: false,
+        };
+        board.reset_domains(adjacency_table);
+        return board;
+    }
+
+    /// Makes every tile possible in every cell with its initial enabler counts
+    fn reset_domains(&mut self, adjacency_table: &AdjacencyTable<D>) {
+        let num_cells = self.vals.len();
+        self.domains.resize(num_cells * self.words_per_cell, 0);
+        let num_tiles = self.num_tiles;
+        for i in 0..num_cells {
+            self.domain_mut(i).fill(num_tiles);
+            self.vals[i].num_possible = num_tiles;
+        }
+        self.tile_counts.possible = vec![num_cells as u32; num_tiles];
+        self.tile_counts.placed = vec![0; num_tiles];
+        if num_tiles == 1 {
+            self.tile_counts.placed[0] = num_cells as u32;
+        }
+        let counts: IdMap<D::Array<usize>> = (0..self.num_tiles)
+            .map(|id| adjacency_table.enabler_counts(id))
+            .collect();
+        for dir in D::all() {
+            let supports = &mut self.supports[dir];
+            supports.clear();
+            supports.reserve(num_cells * self.num_tiles);
+            for _ in 0..num_cells {
+                supports.extend(counts.iter().map(|counts| counts[dir] as Support));
+            }
+        }
+    }
+
+    /// Recalculates the entropy of every cell from the weights of its possible tiles
+    fn reset_probabilities(&mut self, weights: &Weights<D::Loc>) {
+        for i in 0..self.vals.len() {
+            self.recompute_probability(i, weights);
+        }
+    }
+
+    /// Undoes the changes on the trail after `trail_start`, newest first. Returns the
+    /// cells that changed, their probabilities still need to be recomputed
+    fn undo(&mut self, trail_start: usize) -> Vec<usize> {
+        let mut restored = Vec::new();
+        while self.trail.len() > trail_start {
+            match self.trail.pop_back().unwrap() {
+                TrailEntry::Removed { i, tile } => {
+                    self.restore_tile(i, tile);
+                    restored.push(i);
+                }
+                TrailEntry::Support { dir, index } => self.supports[dir][index] += 1,
+                TrailEntry::Collapsed { i } => {
+                    self.vals[i].collapsed_to = None;
+                    restored.push(i);
+                }
+            }
+        }
+        return restored;
+    }
+
+    /// Adds a removed tile back to cell `i`, the inverse of [`Board::remove_tile`] except
+    /// for the probabilities
+    fn restore_tile(&mut self, i: usize, tile: TileId) {
+        match self.vals[i].num_possible {
+            1 => {
+                let placed = self.domain(i).iter().next().unwrap();
+                self.tile_counts.placed[placed] -= 1;
+            }
+            0 => self.tile_counts.placed[tile] += 1,
+            _ => {}
+        }
+        self.tile_counts.possible[tile] += 1;
+        self.domain_mut(i).insert(tile);
+        self.vals[i].num_possible += 1;
+    }
+
+    /// Replaces the tile of the collapsed cell `i` without propogating the change
+    fn replace_tile(&mut self, i: usize, tile: TileId, weights: &Weights<D::Loc>) {
+        let previous = self.vals[i]
+            .collapsed_to
+            .expect("only collapsed cells are replaced");
+        self.tile_counts.placed[previous] -= 1;
+        self.tile_counts.possible[previous] -= 1;
+        self.tile_counts.placed[tile] += 1;
+        self.tile_counts.possible[tile] += 1;
+        let mut domain = self.domain_mut(i);
+        domain.remove(previous);
+        domain.insert(tile);
+        self.vals[i].collapsed_to = Some(tile);
+        self.recompute_probability(i, weights);
+    }
+
+    /// Recounts the enablers of every tile in cell `i` from the domains of its neighbors.
+    /// Directions without a neighbor keep every enabler
+    fn recompute_supports(&mut self, i: usize, adjacency_table: &AdjacencyTable<D>) {
+        let start = i * self.num_tiles;
+        for dir in D::all() {
+            let Some(j) = self.neighbor(i, dir) else {
+                for tile in 0..self.num_tiles {
+                    self.supports[dir][start + tile] =
+                        adjacency_table.enabler_counts(tile)[dir] as Support;
+                }
+                continue;
+            };
+            let domain =
+                TileSet::new(&self.domains[j * self.words_per_cell..(j + 1) * self.words_per_cell]);
+            let supports = &mut self.supports[dir][start..start + self.num_tiles];
+            supports.fill(0);
+            for enabler in domain.iter() {
+                for &tile in adjacency_table.enabled_by(enabler, -dir) {
+                    supports[tile] += 1;
+                }
+            }
+        }
+    }
+
+    fn recompute_probability(&mut self, i: usize, weights: &Weights<D::Loc>) {
+        let loc = self.vals[i].loc;
+        let probability_dict =
+            ProbabilityDict::new(self.domain(i).iter().map(|id| weights.get(loc, id)));
+        self.vals[i].probability_dict = probability_dict;
+    }
+
+    #[inline]
+    fn index(&self, loc: D::Loc) -> usize {
+        return D::index(loc, self.dims);
+    }
+
+    fn inbounds(&self, loc: D::Loc) -> bool {
+        return D::inbounds(loc, self.dims);
+    }
+
+    /// The cells missing a neighbor in some direction
+    fn edge_cells(&self) -> Vec<usize> {
+        return (0..self.len())
+            .filter(|&i| {
+                D::all()
+                    .into_iter()
+                    .any(|dir| self.neighbor(i, dir).is_none())
+            })
+            .collect();
+    }
+
+    /// The index of the neighbor of cell `i` in `dir`, wrapped around the edges of the board
+    /// on periodic axes. `None` if there is no neighbor there
+    #[inline]
+    fn neighbor(&self, i: usize, dir: D) -> Option<usize> {
+        let neighbor = dir.neighbor(self.vals[i].loc, self.dims, self.periodic)?;
+        return Some(self.index(neighbor));
+    }
+
+    fn domain(&self, i: usize) -> TileSet<'_> {
+        let start = i * self.words_per_cell;
+        return TileSet::new(&self.domains[start..start + self.words_per_cell]);
+    }
+
+    fn domain_mut(&mut self, i: usize) -> TileSetMut<'_> {
+        let start = i * self.words_per_cell;
+        return TileSetMut::new(&mut self.domains[start..start + self.words_per_cell]);
+    }
+
+    fn view<'a>(&'a self, i: usize, weights: &'a Weights<D::Loc>) -> CellView<'a, D::Loc> {
+        return CellView {
+            cell: &self.vals[i],
+            domain: self.domain(i),
+            weights,
+        };
+    }
+
+    /// Removes a tile from the domain of cell `i`. Returns whether it was possible
+    fn remove_tile(&mut self, i: usize, tile: TileId, weights: &Weights<D::Loc>) -> bool {
+        if !self.domain_mut(i).remove(tile) {
+            return false;
+        }
+        if self.recording {
+            self.trail.push_back(TrailEntry::Removed { i, tile });
+        }
+        let cell = &mut self.vals[i];
+        cell.num_possible -= 1;
+        cell.probability_dict.remove(weights.get(cell.loc, tile));
+        let num_possible = cell.num_possible;
+        if cell.probability_dict.needs_recompute() {
+            self.recompute_probability(i, weights);
+        }
+        self.tile_counts.possible[tile] -= 1;
+        match num_possible {
+            1 => {
+                let placed = self.domain(i).iter().next().unwrap();
+                self.tile_counts.placed[placed] += 1;
+            }
+            0 => self.tile_counts.placed[tile] -= 1,
+            _ => {}
+        }
+        return true;
+    }
+
+    /// Picks one of the allowed tiles of cell `i` with a probability proportional to
+    /// its weight. Returns `None` if there are no allowed tiles
+    fn choose_collapse_tile(
+        &self,
+        i: usize,
+        weights: &Weights<D::Loc>,
+        rng: &mut WfcRng,
+    ) -> Option<TileId> {
+        if self.vals[i].collapsed() {
+            unreachable!("Cell has already been collapsed");
+        }
+        let domain = self.domain(i);
+        let loc = self.vals[i].loc;
+        let total: Weight = domain.iter().map(|id| weights.get(loc, id)).sum();
+        if total <= 0.0 {
+            // every remaining tile has a weight of zero, fallback to uniform
+            return domain.iter().choose(rng);
+        }
+        let mut remaining = rng.gen_range(0.0..total);
+        let mut last_weighted = None;
+        for id in domain.iter() {
+            let weight = weights.get(loc, id);
+            if weight <= 0.0 {
+                continue;
+            }
+            if remaining < weight {
+                return Some(id);
+            }
+            remaining -= weight;
+            last_weighted = Some(id);
+        }
+        // rounding left the sample just past the end
+        return last_weighted;
+    }
+
+    fn collapse(
+        &mut self,
+        i: usize,
+        weights: &Weights<D::Loc>,
+        rng: &mut WfcRng,
+    ) -> Result<Events<D>, WfcError<D::Loc>> {
+        let fin: TileId =
+            self.choose_collapse_tile(i, weights, rng)
+                .ok_or(WfcError::Contradiction {
+                    loc: self.vals[i].loc,
+                })?;
+        return self.collapse_to(i, fin, weights);
+    }
+
+    /// Removes every tile but `fin` from cell `i`. If `fin` was already removed the
+    /// cell is left without any tiles
+    fn collapse_to(
+        &mut self,
+        i: usize,
+        fin: TileId,
+        weights: &Weights<D::Loc>,
+    ) -> Result<Events<D>, WfcError<D::Loc>> {
+        let loc = self.vals[i].loc;
+        self.vals[i].collapsed_to = Some(fin);
+        if self.recording {
+            self.trail.push_back(TrailEntry::Collapsed { i });
+        }
+        let removed_tile_ids: Vec<TileId> = self.domain(i).iter().filter(|&id| id != fin).collect();
+        for &tile in &removed_tile_ids {
+            self.remove_tile(i, tile, weights);
+        }
+        if !self.domain(i).contains(fin) {
+            return Err(WfcError::Contradiction { loc });
+        }
+        return Ok(TileRemovalEvent::from_list_of_removed_tiles(
+            removed_tile_ids,
+            loc,
+        ));
+    }
+
+    /// Removes a single tile from the domain of cell `i`
+    fn ban(
+        &mut self,
+        i: usize,
+        tile: TileId,
+        weights: &Weights<D::Loc>,
+    ) -> Result<Events<D>, WfcError<D::Loc>> {
+        if !self.remove_tile(i, tile, weights) {
+            return Ok(Vec::new());
+        }
+        let cell = &self.vals[i];
+        if cell.num_possible == 0 {
+            return Err(WfcError::Contradiction { loc: cell.loc });
+        }
+        return Ok(vec![TileRemovalEvent::new(tile, cell.loc)]);
+    }
+
+    /// Removes every tile not in `allowed` from the domain of cell `i`
+    fn restrict(
+        &mut self,
+        i: usize,
+        allowed: TileSet,
+        weights: &Weights<D::Loc>,
+    ) -> Result<Events<D>, WfcError<D::Loc>> {
+        let removed_tile_ids: Vec<TileId> = self
+            .domain(i)
+            .iter()
+            .filter(|&id| !allowed.contains(id))
+            .collect();
+        for &tile in &removed_tile_ids {
+            self.remove_tile(i, tile, weights);
+        }
+        let cell = &self.vals[i];
+        if cell.num_possible == 0 {
+            return Err(WfcError::Contradiction { loc: cell.loc });
+        }
+        return Ok(TileRemovalEvent::from_list_of_removed_tiles(
+            removed_tile_ids,
+            cell.loc,
+        ));
+    }
+
+    /// Removes the support of `enabler` in the neighboring cell in `-from_dir` from cell `i`
+    ///
+    /// Returns the removal events for the tiles that lost their last enabler in that
+    /// direction, or a Contradiction if that leaves the cell without any possible tiles
+    fn remove_enabler(
+        &mut self,
+        i: usize,
+        // the tile id of the enabler that may or may not
+        // have been enabling some of our tiles
+        enabler: TileId,
+        // the dir from enabler to us
+        from_dir: D,
+        adjacency_table: &AdjacencyTable<D>,
+        weights: &Weights<D::Loc>,
+    ) -> Result<Option<Events<D>>, WfcError<D::Loc>> {
+        let start = i * self.num_tiles;
+        let mut removed_tiles = Vec::new();
+        // the tiles the enabler enables pointing towards us
+        for &id in adjacency_table.enabled_by(enabler, from_dir) {
+            if !self.domain(i).contains(id) {
+                log::trace!("tile {id} already removed");
+                continue;
+            }
+            // count in opposite dir i.e. pointing towards enabler
+            let count = &mut self.supports[-from_dir][start + id];
+            assert!(*count != 0);
+            *count -= 1;
+            if self.recording {
+                self.trail.push_back(TrailEntry::Support {
+                    dir: -from_dir,
+                    index: start + id,
+                });
+            }
+            // this tile is incompatible with all of the remaining tiles
+            // of the neighbor in that dir
+            if *count == 0 {
+                removed_tiles.push(id);
+            }
+        }
+        if removed_tiles.is_empty() {
+            return Ok(None);
+        }
+        for &tile in &removed_tiles {
+            self.remove_tile(i, tile, weights);
+        }
+        let cell = &self.vals[i];
+        if cell.num_possible == 0 {
+            return Err(WfcError::Contradiction { loc: cell.loc });
+        }
+        return Ok(Some(TileRemovalEvent::from_list_of_removed_tiles(
+            removed_tiles,
+            cell.loc,
+        )));
+    }
+}
+
+impl Board<CardinalDirs> {
+    fn grid(&self) -> Grid {
+        return Grid(self.dims);
+    }
+
+    /// Whether `loc` is on the board, unlike [`Board::inbounds`] it can be negative
+    pub fn contains(&self, loc: IVec2) -> bool {
+        return loc.cmpge(IVec2::ZERO).all() && loc.cmplt(self.dims.as_ivec2()).all();
+    }
+
+    fn wrap(&self, loc: IVec2) -> IVec2 {
+        let dims = self.dims.as_ivec2();
+        let wrapped = IVec2::new(loc.x.rem_euclid(dims.x), loc.y.rem_euclid(dims.y));
+        return IVec2::select(self.periodic, wrapped, loc);
+    }
+}
+
+impl Index<IVec2> for Board<CardinalDirs> {
+    type Output = Cell;
+
+    fn index(&self, index: IVec2) -> &Self::Output {
+        let i = Board::index(self, index.as_uvec2());
+        return &self.vals[i];
+    }
+}
+
+impl IndexMut<IVec2> for Board<CardinalDirs> {
+    fn index_mut(&mut self, index: IVec2) -> &mut Self::Output {
+        let i = Board::index(self, index.as_uvec2());
+        return &mut self.vals[i];
+    }
+}
+
+/// The global weights of the tiles and the [`TileWeights`] that decide their weight in each cell
+#[derive(Debug)]
+struct Weights<L> {
+    frequencies: IdMap<Weight>,
+    tile_weights: Arc<dyn TileWeights<L>>,
+}
+
+impl<L> Weights<L> {
+    #[inline]
+    fn get(&self, loc: L, tile: TileId) -> Weight {
+        return self.tile_weights.weight(loc, tile, self.frequencies[tile]);
+    }
+}
+
+/// The weights of a model applied to a region of it starting at `min`, see
+/// [`Model::reroll_region`]
+#[derive(Debug)]
+struct RegionWeights {
+    tile_weights: Arc<dyn TileWeights>,
+    min: UVec2,
+}
+
+impl TileWeights for RegionWeights {
+    fn weight(&self, loc: UVec2, tile: TileId, frequency: Weight) -> Weight {
+        return self.tile_weights.weight(self.min + loc, tile, frequency);
+    }
+}
+
+/// The heuristic of a model with dimensions `dims` applied to a region of it starting at
+/// `min`, see [`Model::reroll_region`]
+#[derive(Debug)]
+struct RegionHeuristic {
+    heuristic: Arc<dyn CellHeuristic>,
+    min: UVec2,
+    dims: UVec2,
+}
+
+impl CellHeuristic for RegionHeuristic {
+    fn priority(&self, cell: &Cell, _dims: UVec2) -> f32 {
+        let cell = Cell {
+            loc: self.min + cell.loc,
+            ..cell.clone()
+        };
+        return self.heuristic.priority(&cell, self.dims);
+    }
+}
+
+/// The number of weights removed from a [`ProbabilityDict`] before it is recalculated
+/// from scratch, so the rounding errors of the subtractions don't pile up
+const RECOMPUTE_INTERVAL: u32 = 32;
+
+/// The weights of the possible tiles of a cell summarized for calculating its entropy
+#[derive(Debug, Clone, Copy)]
+pub struct ProbabilityDict {
+    total_shannons: f64,
+    pub total_count: Weight,
+    /// the number of weights removed since the sums were calculated
+    removals: u32,
+}
+
+impl ProbabilityDict {
+    pub(crate) fn new(weights: impl IntoIterator<Item = Weight>) -> Self {
+        let mut total_shannons = 0.0;
+        let mut total_count = 0.0;
+        for weight in weights {
+            total_shannons += Self::partial_shannon(weight);
+            total_count += weight;
+        }
+        return Self {
+            total_shannons,
+            total_count,
+            removals: 0,
+        };
+    }
+
+    /// one of the `w0 * log(w0)` terms in the simplified entropy equation
+    #[inline]
+    fn partial_shannon(weight: Weight) -> f64 {
+        if weight <= 0.0 {
+            // lim w->0 of w*log(w) is 0, avoids 0 * -inf = NaN
+            return 0.0;
+        }
+        return weight * weight.log2();
+    }
+
+    /// Calculates the Shannon Entropy `H(x) = -P(x)*log(P(x))`
+    ///
+    /// The Shannon Entropy For set `x` of items `x0..xn` is
+    /// `H(x) = H(x0) + H(x1) ... H(xn)`
+    ///
+    /// The Probability of an entry `xi` is `P(xi) = wi / sum(w)`
+    /// where `wi` is the weight of xi
+    /// and `sum(w)` is the sum of the weights i.e. `self.total_count`
+    ///
+    /// The simplified Entropy Equation is then:
+    /// `H(x) = log(sum(w))- (w0*log(w0) -w1*log(w1) ... -wn*log(wn)) / sum(w)`
+    ///
+    /// Returns 0 when every remaining weight is 0, so priorities stay comparable
+    pub fn entropy(&self) -> f32 {
+        if self.total_count <= 0.0 {
+            return 0.0;
+        }
+        // Calculate entropy using simplified shannon entropy
+        return (self.total_count.log2() - (self.total_shannons / self.total_count)) as f32;
+    }
+
+    /// Removes a tile with the given weight
+    pub(crate) fn remove(&mut self, weight: Weight) {
+        self.total_count -= weight;
+        self.total_shannons -= Self::partial_shannon(weight);
+        self.removals += 1;
+    }
+
+    /// Whether enough weights have been removed that the sums should be recalculated
+    pub(crate) fn needs_recompute(&self) -> bool {
+        return self.removals >= RECOMPUTE_INTERVAL;
+    }
+}
+
+/// Sentinel position of cells that aren't in the heap
+const NOT_IN_HEAP: usize = usize::MAX;
+
+/// An indexed binary min heap with at most one entry per cell
+///
+/// Pushing an entry for a cell that is already in the heap updates its entropy in
+/// place, so the heap never holds more entries than there are cells
+#[derive(Debug, Clone)]
+pub struct MinEntropyHeap {
+    entries: Vec<EntropyEntry>,
+    /// the index into `entries` of each cell
+    positions: Vec<usize>,
+}
+
+impl MinEntropyHeap {
+    pub fn new(num_cells: usize) -> Self {
+        return Self {
+            entries: Vec::new(),
+            positions: vec![NOT_IN_HEAP; num_cells],
+        };
+    }
+
+    /// Adds the entry or updates the entropy of the existing entry for its cell
+    fn push(&mut self, e: EntropyEntry) {
+        let cell = e.index;
+        let pos = self.positions[cell];
+        if pos == NOT_IN_HEAP {
+            self.entries.push(e);
+            self.positions[cell] = self.entries.len() - 1;
+            self.sift_up(self.entries.len() - 1);
+            return;
+        }
+        let old = std::mem::replace(&mut self.entries[pos], e);
+        match e.cmp(&old) {
+            Ordering::Less => self.sift_up(pos),
+            Ordering::Greater => self.sift_down(pos),
+            Ordering::Equal => {}
+        }
+    }
+
+    fn pop(&mut self) -> Option<EntropyEntry> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let last = self.entries.len() - 1;
+        self.swap(0, last);
+        let entry = self.entries.pop().unwrap();
+        self.positions[entry.index] = NOT_IN_HEAP;
+        self.sift_down(0);
+        return Some(entry);
+    }
+
+    /// Removes the entry of the cell at index `cell` if there is one
+    fn remove(&mut self, cell: usize) {
+        let pos = self.positions[cell];
+        if pos == NOT_IN_HEAP {
+            return;
+        }
+        let last = self.entries.len() - 1;
+        self.swap(pos, last);
+        self.entries.pop();
+        self.positions[cell] = NOT_IN_HEAP;
+        if pos < self.entries.len() {
+            // the moved entry may belong above or below its new position
+            self.sift_up(pos);
+            self.sift_down(pos);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        return self.entries.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.entries.is_empty();
+    }
+
+    fn clear(&mut self) {
+        for entry in self.entries.drain(..) {
+            self.positions[entry.index] = NOT_IN_HEAP;
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.entries.swap(a, b);
+        self.positions[self.entries[a].index] = a;
+        self.positions[self.entries[b].index] = b;
+    }
+
+    fn sift_up(&mut self, mut pos: usize) {
+        while pos > 0 {
+            let parent = (pos - 1) / 2;
+            if self.entries[pos].cmp(&self.entries[parent]).is_ge() {
+                break;
+            }
+            self.swap(pos, parent);
+            pos = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut pos: usize) {
+        loop {
+            let left = 2 * pos + 1;
+            let right = left + 1;
+            let mut min = pos;
+            if left < self.entries.len() && self.entries[left].cmp(&self.entries[min]).is_lt() {
+                min = left;
+            }
+            if right < self.entries.len() && self.entries[right].cmp(&self.entries[min]).is_lt() {
+                min = right;
+            }
+            if min == pos {
+                break;
+            }
+            self.swap(pos, min);
+            pos = min;
+        }
+    }
+}
+
+/// `entropy` is the priority given by the model's [`CellHeuristic`] to the cell at `index`
+#[derive(Debug, Clone, Copy)]
+struct EntropyEntry {
+    entropy: f32,
+    index: usize,
+}
+impl PartialEq for EntropyEntry {
+    fn eq(&self, other: &Self) -> bool {
+        return self.cmp(other).is_eq();
+    }
+}
+
+impl Eq for EntropyEntry {}
+
+impl PartialOrd for EntropyEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+impl Ord for EntropyEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        match self.entropy.total_cmp(&other.entropy) {
+            // resolve equality by comparing location
+            Ordering::Equal => self.index.cmp(&other.index),
+            lt_or_gt => lt_or_gt,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TileRemovalEvent<L = UVec2> {
+    pub tile_id: usize,
+    pub cell_loc: L,
+}
+
+impl<L: Copy> TileRemovalEvent<L> {
+    pub fn new(tile_id: TileId, cell_loc: L) -> Self {
+        return Self { tile_id, cell_loc };
+    }
+    pub fn from_list_of_removed_tiles(removed_tiles: Vec<TileId>, loc: L) -> Vec<Self> {
+        return removed_tiles
+            .iter()
+            .map(|&removed_tile_id| Self::new(removed_tile_id, loc))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::simple_patterns::{construct_simple_patterns, CHARS};
+    use glam::BVec2;
+
+    use super::*;
+
+    fn simple_model(dims: UVec2, seed: u64) -> Model {
+        let data = construct_simple_patterns();
+        return Model::from_seed(data.adjacency_rules, data.tile_frequencies, dims, seed);
+    }
+
+    fn collapsed_tiles(model: &Model) -> Vec<Option<TileId>> {
+        return model.iter_cells().map(|cell| cell.collapsed_to).collect();
+    }
+
+    fn all_adjacency_rules_satisfied(model: &Model) {
+        for (i, cell) in model.iter_cells().enumerate() {
+            let cell_loc = cell.loc;
+            for dir in CardinalDirs::all() {
+                // if the adjacent cell exists
+                if let Some(j) = model.board.neighbor(i, dir) {
+                    let adj_cell = model.board.view(j, &model.weights);
+                    let adjacent_cell_loc = adj_cell.loc;
+                    let mut cell_domain_in_dir: Vec<usize> = cell
+                        .domain
+                        .iter()
+                        .flat_map(|tile_id| model.adjacency_table.enabled_by(tile_id, dir))
+                        .copied()
+                        .collect();
+                    cell_domain_in_dir.sort();
+                    cell_domain_in_dir.dedup();
+                    for adj_allowed_tile_id in adj_cell.domain.iter() {
+                        assert!(cell_domain_in_dir.contains(&adj_allowed_tile_id), "cell at {cell_loc:?} with domain {:?} in direction {dir:?} has neighbor at {adjacent_cell_loc} with possible tile {} that should not be allowed", cell_domain_in_dir.iter().map(|&tile_id| CHARS[tile_id]).collect::<Vec<&str>>(), CHARS[adj_allowed_tile_id]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn adjacency_rules_fulfilled_always() {
+        for seed in 0..8 {
+            let mut model = simple_model(UVec2::splat(30), seed);
+            while model.remaining_uncollapsed > 0 {
+                if model.wave.is_empty() {
+                    all_adjacency_rules_satisfied(&model);
+                }
+                // the rules hold up to the contradiction, which is covered below
+                if model.step().is_err() {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_same_output() {
+        let mut a = simple_model(UVec2::splat(20), 0);
+        let mut b = simple_model(UVec2::splat(20), 0);
+        a.run().unwrap();
+        b.run().unwrap();
+        assert_eq!(collapsed_tiles(&a), collapsed_tiles(&b));
+    }
+
+    #[test]
+    fn contradictions_are_reported() {
+        let mut contradictions = 0;
+        for seed in 0..10 {
+            let mut model = simple_model(UVec2::splat(40), seed);
+            match model.run() {
+                Ok(()) => all_adjacency_rules_satisfied(&model),
+                Err(err @ WfcError::Contradiction { loc }) => {
+                    assert!(model.get_cell(loc).unwrap().domain.is_empty());
+                    // model stays failed
+                    assert_eq!(model.step(), Err(err));
+                    contradictions += 1;
+                }
+                Err(err) => panic!("unexpected error {err}"),
+            }
+        }
+        assert!(contradictions > 0);
+    }
+
+    #[test]
+    fn collapse_is_weighted_by_counts() {
+        for seed in 0..20 {
+            let data = construct_simple_patterns();
+            let mut model = Model::from_seed(
+                data.adjacency_rules,
+                vec![0.0, 0.0, 5.0, 0.0, 0.0],
+                UVec2::ONE,
+                seed,
+            );
+            model.collapse_cell().unwrap();
+            assert_eq!(model.get_cell(UVec2::ZERO).unwrap().collapsed_to, Some(2));
+        }
+    }
+
+    #[test]
+    fn weight_overrides_update_cells() {
+        let model = simple_model(UVec2::splat(4), 0).with_weight_overrides([(0, 10.0), (3, 0.0)]);
+        assert_eq!(model.tile_frequencies(), &vec![10.0, 2.0, 2.0, 0.0, 2.0]);
+        for cell in model.iter_cells() {
+            let weights: Vec<Weight> = cell.domain.iter().map(|id| cell.weight(id)).collect();
+            assert_eq!(weights, vec![10.0, 2.0, 2.0, 0.0, 2.0]);
+            assert_eq!(cell.probability_dict.total_count, 16.0);
+            assert!(cell.probability_dict.entropy().is_finite());
+        }
+    }
+
+    /// Checks the running sums of every cell against the weights of its possible tiles
+    fn probabilities_match_domains(model: &Model) {
+        for cell in model.iter_cells().filter(|cell| !cell.collapsed()) {
+            let weights: Vec<Weight> = cell.domain.iter().map(|id| cell.weight(id)).collect();
+            let expected = ProbabilityDict::new(weights);
+            assert!((cell.probability_dict.total_count - expected.total_count).abs() < 1e-9);
+            assert!((cell.probability_dict.entropy() - expected.entropy()).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn fractional_weights_stay_accurate() {
+        let weights = [(0, 0.05), (1, 0.3), (2, 1e3), (3, 1.7), (4, 0.001)];
+        let mut model = simple_model(UVec2::splat(12), 0).with_weight_overrides(weights);
+        while !model.is_done() {
+            model.step().unwrap();
+            probabilities_match_domains(&model);
+        }
+    }
+
+    #[test]
+    fn weights_can_change_mid_solve() {
+        use crate::simple_patterns::BLANK;
+        let mut model = simple_model(UVec2::splat(10), 0);
+        for _ in 0..20 {
+            model.step().unwrap();
+        }
+        model.set_weights([(BLANK, 0.05)]);
+        assert_eq!(model.tile_frequencies()[BLANK], 0.05);
+        probabilities_match_domains(&model);
+        for cell in model
+            .iter_cells()
+            .filter(|cell| cell.domain.contains(BLANK))
+        {
+            assert_eq!(cell.weight(BLANK), 0.05);
+        }
+        model.run().unwrap();
+        all_adjacency_rules_satisfied(&model);
+    }
+
+    #[test]
+    fn weight_map_biases_tiles_by_location() {
+        use crate::simple_patterns::BLANK;
+        use crate::weights::WeightMap;
+        use image::{GrayImage, Luma};
+        let dims = UVec2::splat(10);
+        // blank tiles are only picked in the left half when nothing else is allowed
+        let map = GrayImage::from_fn(2, 1, |x, _| Luma([if x == 0 { 0 } else { 255 }]));
+        let mut model =
+            simple_model(dims, 0).with_tile_weights(WeightMap::new(dims).with_map(BLANK, map));
+        let left = model.get_cell(UVec2::new(0, 0)).unwrap();
+        let right = model.get_cell(UVec2::new(9, 0)).unwrap();
+        assert_eq!(left.weight(BLANK), 0.0);
+        assert!(right.weight(BLANK) > 0.0);
+        assert!(left.probability_dict.total_count < right.probability_dict.total_count);
+
+        model.solve(0, 50).unwrap();
+        all_adjacency_rules_satisfied(&model);
+        let blanks = |xs: std::ops::Range<u32>| {
+            model
+                .iter_cells()
+                .filter(|cell| xs.contains(&cell.loc.x) && cell.collapsed_to == Some(BLANK))
+                .count()
+        };
+        assert!(blanks(0..5) < blanks(5..10));
+    }
+
+    #[test]
+    fn domains_track_possible_tiles() {
+        let mut model = simple_model(UVec2::splat(4), 0);
+        let cell = model.get_cell(UVec2::ZERO).unwrap();
+        assert_eq!(cell.num_possible(), 5);
+        assert_eq!(cell.domain.iter().collect::<Vec<_>>(), vec![0, 1, 2, 3, 4]);
+
+        model.ban(UVec2::ZERO, 1).unwrap();
+        let cell = model.get_cell(UVec2::ZERO).unwrap();
+        assert!(!cell.domain.contains(1));
+        assert_eq!(cell.num_possible(), cell.domain.len());
+        assert_eq!(cell.probability_dict.total_count, 7.0);
+
+        model.run().unwrap();
+        for cell in model.iter_cells() {
+            assert!(cell.is_decided());
+            assert_eq!(
+                cell.domain.iter().collect::<Vec<_>>(),
+                vec![cell.collapsed_to.unwrap()]
+            );
+        }
+    }
+
+    #[test]
+    fn backtracking_resolves_contradictions() {
+        let (mut solved, mut solved_with_backtracking) = (0, 0);
+        for seed in 0..20 {
+            if simple_model(UVec2::splat(20), seed).run().is_ok() {
+                solved += 1;
+            }
+            let mut model =
+                simple_model(UVec2::splat(20), seed).with_backtracking(Backtracking::default());
+            if model.run().is_ok() {
+                all_adjacency_rules_satisfied(&model);
+                solved_with_backtracking += 1;
+            }
+        }
+        // a good share of the seeds hit contradictions on this board
+        assert!(solved < 18);
+        assert!(solved_with_backtracking > solved);
+        assert!(solved_with_backtracking >= 15);
+    }
+
+    #[test]
+    fn backtracking_restores_the_board() {
+        let mut model =
+            simple_model(UVec2::splat(10), 0).with_backtracking(Backtracking::default());
+        let settle = |model: &mut Model| {
+            model.step().unwrap();
+            while !model.wave.is_empty() {
+                model.step().unwrap();
+            }
+        };
+        for _ in 0..10 {
+            settle(&mut model);
+        }
+        let domains = model.board.domains.clone();
+        let supports = model.board.supports.clone();
+        let tiles = collapsed_tiles(&model);
+        let remaining_uncollapsed = model.remaining_uncollapsed;
+        let unchecked_collapses = model.unchecked_collapses;
+
+        settle(&mut model);
+        assert_eq!(model.backtracks, 0);
+        let &Decision { i, tile_id, .. } = model.decisions.back().unwrap();
+        assert!(model.backtrack(Backtracking::default()));
+
+        // only the banned tile differs, its removal hasn't been propogated yet
+        let mut expected = domains;
+        let words = model.board.words_per_cell;
+        TileSetMut::new(&mut expected[i * words..(i + 1) * words]).remove(tile_id);
+        assert_eq!(model.board.domains, expected);
+        assert_eq!(model.board.supports, supports);
+        assert_eq!(collapsed_tiles(&model), tiles);
+        assert_eq!(model.remaining_uncollapsed, remaining_uncollapsed);
+        assert_eq!(model.unchecked_collapses, unchecked_collapses);
+        probabilities_match_domains(&model);
+        for tile in 0..5 {
+            let cells: Vec<_> = model
+                .iter_cells()
+                .filter(|cell| cell.domain.contains(tile))
+                .collect();
+            let possible = cells.len() as u32;
+            let placed = cells.iter().filter(|cell| cell.num_possible() == 1).count() as u32;
+            assert_eq!(model.board.tile_counts.possible[tile], possible);
+            assert_eq!(model.board.tile_counts.placed[tile], placed);
+        }
+        model.run().unwrap();
+        all_adjacency_rules_satisfied(&model);
+    }
+
+    #[test]
+    fn restarts_until_solved() {
+        let mut restarted = 0;
+        for seed in 0..20 {
+            let mut model = simple_model(UVec2::splat(20), seed);
+            let report = model.solve(seed, 50).unwrap();
+            all_adjacency_rules_satisfied(&model);
+            if report.attempts > 1 {
+                restarted += 1;
+            }
+
+            let mut reproduced = simple_model(UVec2::splat(20), report.seed);
+            reproduced.run().unwrap();
+            assert_eq!(collapsed_tiles(&model), collapsed_tiles(&reproduced));
+        }
+        // some of the seeds hit a contradiction on the first attempt
+        assert!(restarted > 0);
+    }
+
+    #[test]
+    fn periodic_after_setting_an_edge_tile() {
+        let mut model = simple_model(UVec2::splat(6), 0).with_backtracking(Backtracking::default());
+        let tile = 1;
+        model.set_tile(UVec2::new(0, 2), tile).unwrap();
+        let mut model = model.with_periodic(BVec2::new(true, true));
+        assert!(model.contradiction.is_none());
+
+        // the supports of the tiles still possible match their neighbors
+        let supports = model.board.supports.clone();
+        for i in 0..model.board.len() {
+            model.board.recompute_supports(i, &model.adjacency_table);
+        }
+        let num_tiles = model.board.num_tiles;
+        for i in 0..model.board.len() {
+            for tile in model.board.domain(i).iter() {
+                for dir in CardinalDirs::all() {
+                    let index = i * num_tiles + tile;
+                    assert_eq!(model.board.supports[dir][index], supports[dir][index]);
+                }
+            }
+        }
+        // the cell on the opposite edge is now the left neighbor of the set tile
+        let across = model.get_cell(UVec2::new(5, 2)).unwrap();
+        assert!(across
+            .domain
+            .iter()
+            .all(|t| model
+                .adjacency_table
+                .is_allowed(tile, t, CardinalDirs::Left)));
+
+        model.run().unwrap();
+        all_adjacency_rules_satisfied(&model);
+    }
+
+    #[test]
+    fn periodic_neighbors_wrap() {
+        let model = simple_model(UVec2::new(4, 3), 0).with_periodic(BVec2::new(true, false));
+        for dir in CardinalDirs::all() {
+            let neighbor = model.board.neighbor(0, dir);
+            match dir {
+                CardinalDirs::Left => assert_eq!(neighbor, Some(3)),
+                CardinalDirs::Up => assert_eq!(neighbor, None),
+                _ => assert!(neighbor.is_some()),
+            }
+        }
+    }
+
+    #[test]
+    fn periodic_output_tiles_seamlessly() {
+        let mut model = simple_model(UVec2::splat(20), 0).with_periodic(BVec2::TRUE);
+        model.solve(0, 50).unwrap();
+        // neighbors across the edges are checked as well
+        all_adjacency_rules_satisfied(&model);
+    }
+
+    #[test]
+    fn set_tiles_are_kept() {
+        use crate::simple_patterns::{BLANK, DL};
+        let mut model = simple_model(UVec2::splat(10), 0);
+        model.set_tile(UVec2::new(3, 0), DL).unwrap();
+        for x in 0..10 {
+            model.ban(UVec2::new(x, 9), BLANK).unwrap();
+        }
+        // constraints survive the restarts
+        model.solve(0, 50).unwrap();
+        all_adjacency_rules_satisfied(&model);
+        assert_eq!(
+            model.get_cell(UVec2::new(3, 0)).unwrap().collapsed_to,
+            Some(DL)
+        );
+        for x in 0..10 {
+            assert_ne!(
+                model.get_cell(UVec2::new(x, 9)).unwrap().collapsed_to,
+                Some(BLANK)
+            );
+        }
+    }
+
+    #[test]
+    fn restricted_cells_only_hold_allowed_tiles() {
+        use crate::simple_patterns::{BLANK, DL, LU, RD, UR};
+        let mut model = simple_model(UVec2::splat(10), 0);
+        let cells = (0..10).map(|x| (UVec2::new(x, 5), [BLANK, LU, UR]));
+        model.restrict_cells(cells).unwrap();
+        for x in 0..10 {
+            let cell = model.get_cell(UVec2::new(x, 5)).unwrap();
+            assert!(!cell.domain.contains(DL) && !cell.domain.contains(RD));
+        }
+        all_adjacency_rules_satisfied(&model);
+        // kept on reset
+        model.solve(0, 50).unwrap();
+        for x in 0..10 {
+            let tile = model
+                .get_cell(UVec2::new(x, 5))
+                .unwrap()
+                .collapsed_to
+                .unwrap();
+            assert!([BLANK, LU, UR].contains(&tile));
+        }
+
+        let mut model = simple_model(UVec2::splat(10), 0);
+        let result = model.restrict_cells([(UVec2::new(2, 3), [])]);
+        assert_eq!(
+            result,
+            Err(WfcError::Contradiction {
+                loc: UVec2::new(2, 3)
+            })
+        );
+        assert_eq!(model.step().err(), result.err());
+    }
+
+    #[test]
+    fn reset_matches_constraints_applied_one_at_a_time() {
+        use crate::simple_patterns::{BLANK, DL, UR};
+        let constrain = |model: &mut Model| {
+            model.set_tile(UVec2::new(3, 2), DL).unwrap();
+            model.set_tile(UVec2::new(7, 6), UR).unwrap();
+            for x in 0..10 {
+                model.ban(UVec2::new(x, 9), BLANK).unwrap();
+            }
+        };
+        let mut fresh = simple_model(UVec2::splat(10), 4);
+        constrain(&mut fresh);
+        let mut reset = simple_model(UVec2::splat(10), 0);
+        constrain(&mut reset);
+        reset.reset(4);
+
+        let domains = |model: &Model| -> Vec<Vec<TileId>> {
+            return model
+                .iter_cells()
+                .map(|cell| cell.domain.iter().collect())
+                .collect();
+        };
+        assert_eq!(domains(&fresh), domains(&reset));
+        probabilities_match_domains(&reset);
+        let (fresh_result, reset_result) = (fresh.run(), reset.run());
+        assert_eq!(fresh_result, reset_result);
+        assert_eq!(collapsed_tiles(&fresh), collapsed_tiles(&reset));
+    }
+
+    #[test]
+    fn from_grid_completes_fixed_cells() {
+        let dims = UVec2::splat(8);
+        let mut original = simple_model(dims, 0);
+        original.solve(0, 50).unwrap();
+        // keep every third cell
+        let tiles: Vec<Option<TileId>> = collapsed_tiles(&original)
+            .into_iter()
+            .enumerate()
+            .map(|(i, tile)| tile.filter(|_| i % 3 == 0))
+            .collect();
+
+        let data = construct_simple_patterns();
+        let mut model = Model::from_grid(
+            data.adjacency_rules,
+            data.tile_frequencies,
+            dims,
+            tiles.clone(),
+            1,
+        )
+        .unwrap();
+        // propogated before the first step
+        let propogated = model
+            .iter_cells()
+            .any(|cell| !cell.collapsed() && cell.num_possible() < 5);
+        assert!(propogated);
+        model.solve(1, 50).unwrap();
+        all_adjacency_rules_satisfied(&model);
+        for (tile, cell) in zip(tiles, model.iter_cells()) {
+            if tile.is_some() {
+                assert_eq!(cell.collapsed_to, tile);
+            }
+        }
+    }
+
+    #[test]
+    fn from_grid_reports_conflicts() {
+        use crate::simple_patterns::{BLANK, DL};
+        let data = construct_simple_patterns();
+        let mut tiles = vec![None; 9];
+        // ┓┓ can't be next to each other
+        tiles[0] = Some(DL);
+        tiles[1] = Some(DL);
+        // ┓ can't be above a blank
+        tiles[5] = Some(DL);
+        tiles[8] = Some(BLANK);
+        let result = Model::from_grid(
+            data.adjacency_rules,
+            data.tile_frequencies,
+            UVec2::splat(3),
+            tiles,
+            0,
+        );
+        let conflicts = vec![
+            Conflict {
+                a: UVec2::new(0, 0),
+                b: UVec2::new(1, 0),
+            },
+            Conflict {
+                a: UVec2::new(2, 1),
+                b: UVec2::new(2, 2),
+            },
+        ];
+        assert_eq!(result.err(), Some(GridError::Conflicts(conflicts)));
+    }
+
+    #[test]
+    fn from_grid_reports_unknown_tiles() {
+        use crate::simple_patterns::BLANK;
+        let data = construct_simple_patterns();
+        let mut tiles = vec![None; 9];
+        tiles[0] = Some(BLANK);
+        tiles[4] = Some(5);
+        let result = Model::from_grid(
+            data.adjacency_rules,
+            data.tile_frequencies,
+            UVec2::splat(3),
+            tiles,
+            0,
+        );
+        let unknown = WfcError::UnknownTile {
+            loc: UVec2::new(1, 1),
+            tile: 5,
+        };
+        assert_eq!(result.err(), Some(GridError::Unsatisfiable(unknown)));
+
+        let mut model = simple_model(UVec2::splat(3), 0);
+        assert_eq!(
+            model.set_tile(UVec2::ZERO, 5),
+            Err(WfcError::UnknownTile {
+                loc: UVec2::ZERO,
+                tile: 5
+            })
+        );
+    }
+
+    #[test]
+    fn reroll_keeps_cells_outside_region() {
+        let (min, max) = (UVec2::new(3, 4), UVec2::new(9, 8));
+        let mut model = simple_model(UVec2::splat(12), 0);
+        model.solve(0, 50).unwrap();
+        let before = collapsed_tiles(&model);
+
+        let report = model.reroll_region(min, max, 7, 50).unwrap();
+        assert!(model.is_done());
+        all_adjacency_rules_satisfied(&model);
+        let after = collapsed_tiles(&model);
+        let mut changed = false;
+        for (i, loc) in model.board.grid().iter_locs().enumerate() {
+            if loc.cmpge(min).all() && loc.cmplt(max).all() {
+                changed |= before[i] != after[i];
+            } else {
+                assert_eq!(
+                    before[i], after[i],
+                    "cell at {loc} outside the region changed"
+                );
+            }
+        }
+        assert!(changed);
+
+        // the reported seed reproduces the reroll
+        let mut again = simple_model(UVec2::splat(12), 0);
+        again.solve(0, 50).unwrap();
+        again.reroll_region(min, max, report.seed, 1).unwrap();
+        assert_eq!(collapsed_tiles(&again), after);
+    }
+
+    #[test]
+    fn failed_reroll_restores_output() {
+        let mut model = simple_model(UVec2::splat(8), 0);
+        model.solve(0, 50).unwrap();
+        let before = collapsed_tiles(&model);
+        let result = model.reroll_region(UVec2::ZERO, UVec2::splat(4), 0, 0);
+        assert_eq!(result, Err(WfcError::AttemptsExhausted { attempts: 0 }));
+        assert!(model.is_done());
+        assert_eq!(collapsed_tiles(&model), before);
+    }
+
+    #[test]
+    fn reroll_keeps_constraints() {
+        use crate::simple_patterns::DL;
+        let (min, max) = (UVec2::splat(3), UVec2::splat(9));
+        let mut model =
+            simple_model(UVec2::splat(12), 0).with_tile_count(DL, TileCount::exactly(6));
+        model.set_tile(UVec2::new(5, 5), DL).unwrap();
+        model.solve(0, 100).unwrap();
+        model.reroll_region(min, max, 1, 100).unwrap();
+        all_adjacency_rules_satisfied(&model);
+        assert_eq!(count_tiles(&model, DL), 6);
+        assert_eq!(
+            model.get_cell(UVec2::new(5, 5)).unwrap().collapsed_to,
+            Some(DL)
+        );
+
+        // the enablers around the region are counted from the new tiles
+        let board = &model.board;
+        for (i, cell) in board.iter().enumerate() {
+            let tile = cell.collapsed_to.unwrap();
+            for dir in CardinalDirs::all() {
+                let Some(j) = board.neighbor(i, dir) else {
+                    continue;
+                };
+                let neighbor_tile = board.vals[j].collapsed_to.unwrap();
+                let supported = model
+                    .adjacency_table
+                    .enabled_by(neighbor_tile, -dir)
+                    .contains(&tile);
+                assert_eq!(
+                    board.supports[dir][i * board.num_tiles + tile],
+                    supported as Support
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unsatisfiable_constraints_are_reported() {
+        let data = construct_simple_patterns();
+        let rules = &data.adjacency_rules;
+        let (a, b) = (0..5)
+            .flat_map(|a| (0..5).map(move |b| (a, b)))
+            .find(|&(a, b)| !rules.is_allowed(a, b, CardinalDirs::Right))
+            .unwrap();
+        let mut model = simple_model(UVec2::splat(4), 0);
+        model.set_tile(UVec2::ZERO, a).unwrap();
+        let loc = UVec2::new(1, 0);
+        assert_eq!(model.set_tile(loc, b), Err(WfcError::Contradiction { loc }));
+        assert_eq!(model.step(), Err(WfcError::Contradiction { loc }));
+
+        let mut model = simple_model(UVec2::splat(4), 0);
+        let banned = (0..5)
+            .map(|tile| model.ban(UVec2::ZERO, tile))
+            .last()
+            .unwrap();
+        assert_eq!(banned, Err(WfcError::Contradiction { loc: UVec2::ZERO }));
+    }
+
+    #[test]
+    fn border_limits_edge_tiles() {
+        use crate::simple_patterns::BLANK;
+        let mut model = simple_model(UVec2::splat(10), 0)
+            .with_border(Border::new().with_side(CardinalDirs::Down, [BLANK]))
+            .unwrap();
+        model.solve(0, 50).unwrap();
+        all_adjacency_rules_satisfied(&model);
+        for x in 0..10 {
+            assert_eq!(
+                model.get_cell(UVec2::new(x, 9)).unwrap().collapsed_to,
+                Some(BLANK)
+            );
+        }
+    }
+
+    #[test]
+    fn border_from_outside_tile_closes_output() {
+        use crate::simple_patterns::BLANK;
+        let rules = construct_simple_patterns().adjacency_rules;
+        let dims = UVec2::splat(10);
+        let mut model = simple_model(dims, 0)
+            .with_border(Border::from_outside(&rules, BLANK))
+            .unwrap();
+        model.solve(0, 50).unwrap();
+        all_adjacency_rules_satisfied(&model);
+        for cell in model.iter_cells() {
+            let tile = cell.collapsed_to.unwrap();
+            for side in CardinalDirs::as_array() {
+                if !model.board.contains(side + cell.loc.as_ivec2()) {
+                    assert!(rules.is_allowed(tile, BLANK, side));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn border_conflicting_with_a_set_tile_fails() {
+        use crate::simple_patterns::{BLANK, DL};
+        let mut model = simple_model(UVec2::splat(10), 0);
+        model.set_tile(UVec2::new(4, 9), DL).unwrap();
+        let border = Border::new().with_side(CardinalDirs::Down, [BLANK]);
+        assert!(matches!(
+            model.with_border(border),
+            Err(WfcError::Contradiction { .. })
+        ));
+    }
+
+    #[test]
+    fn border_skips_periodic_sides() {
+        use crate::simple_patterns::BLANK;
+        let border = Border::new()
+            .with_side(CardinalDirs::Left, [BLANK])
+            .with_side(CardinalDirs::Down, [BLANK]);
+        let mut model = simple_model(UVec2::splat(10), 0)
+            .with_periodic(BVec2::new(true, false))
+            .with_border(border)
+            .unwrap();
+        assert!(model.get_cell(UVec2::new(0, 4)).unwrap().num_possible() > 1);
+        assert_eq!(model.get_cell(UVec2::new(4, 9)).unwrap().num_possible(), 1);
+        model.solve(0, 50).unwrap();
+        all_adjacency_rules_satisfied(&model);
+    }
+
+    fn count_tiles(model: &Model, tile: TileId) -> u32 {
+        return model
+            .iter_cells()
+            .filter(|cell| cell.collapsed_to == Some(tile))
+            .count() as u32;
+    }
+
+    #[test]
+    fn tile_counts_are_kept() {
+        use crate::simple_patterns::{BLANK, DL, UR};
+        let dims = UVec2::splat(10);
+        let mut model = simple_model(dims, 0)
+            .with_tile_count(DL, TileCount::exactly(4))
+            .with_tile_count(UR, TileCount::at_most(6))
+            .with_tile_count(BLANK, TileCount::at_least_fraction(0.3, dims));
+        model.solve(0, 100).unwrap();
+        all_adjacency_rules_satisfied(&model);
+        assert_eq!(count_tiles(&model, DL), 4);
+        assert!(count_tiles(&model, UR) <= 6);
+        assert!(count_tiles(&model, BLANK) >= 30);
+    }
+
+    #[test]
+    fn unreachable_tile_counts_are_reported() {
+        use crate::simple_patterns::BLANK;
+        let mut model =
+            simple_model(UVec2::splat(4), 0).with_tile_count(BLANK, TileCount::at_least(17));
+        assert_eq!(model.step(), Err(WfcError::TileCount { tile: BLANK }));
+    }
+
+    /// The number of connected regions of the walkable cells of a finished model
+    fn count_regions(model: &Model, connectivity: &Connectivity) -> usize {
+        let exits = |i: usize| connectivity.exits(model.board.vals[i].collapsed_to?);
+        let mut visited = vec![false; model.board.len()];
+        let mut regions = 0;
+        for start in 0..model.board.len() {
+            if visited[start] || exits(start).is_none() {
+                continue;
+            }
+            regions += 1;
+            let mut stack = vec![start];
+            visited[start] = true;
+            while let Some(i) = stack.pop() {
+                for dir in CardinalDirs::all() {
+                    let Some(j) = model.board.neighbor(i, dir) else {
+                        continue;
+                    };
+                    if !exits(i).unwrap()[dir] {
+                        continue;
+                    }
+                    if !visited[j] && exits(j).is_some_and(|exits| exits[-dir]) {
+                        visited[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+        }
+        return regions;
+    }
+
+    #[test]
+    fn walkable_tiles_are_connected() {
+        use crate::simple_patterns::BLANK;
+        let connectivity = Connectivity::new([BLANK]);
+        for seed in 0..5 {
+            let mut model =
+                simple_model(UVec2::splat(12), seed).with_connectivity(connectivity.clone());
+            model.solve(seed, 100).unwrap();
+            all_adjacency_rules_satisfied(&model);
+            assert_eq!(count_regions(&model, &connectivity), 1);
+        }
+        // checking rarely still checks the finished board
+        let connectivity = connectivity.with_check_interval(16);
+        for seed in 0..5 {
+            let mut model =
+                simple_model(UVec2::splat(12), seed).with_connectivity(connectivity.clone());
+            model.solve(seed, 100).unwrap();
+            assert_eq!(count_regions(&model, &connectivity), 1);
+        }
+    }
+
+    #[test]
+    fn connectivity_follows_exits() {
+        use crate::simple_patterns::{DL, LU, RD, UR};
+        use CardinalDirs::*;
+        // the pipes must form a single loop
+        let connectivity = Connectivity::default()
+            .with_exits(DL, [Down, Left])
+            .with_exits(LU, [Left, Up])
+            .with_exits(RD, [Right, Down])
+            .with_exits(UR, [Up, Right]);
+        let mut model = simple_model(UVec2::splat(8), 0)
+            .with_connectivity(connectivity.clone())
+            .with_backtracking(Backtracking::default());
+        model.solve(0, 100).unwrap();
+        all_adjacency_rules_satisfied(&model);
+        assert_eq!(count_regions(&model, &connectivity), 1);
+    }
+
+    #[test]
+    fn tiles_without_enablers_are_banned() {
+        use CardinalDirs::*;
+        let mut adjacency_rules = AdjacencyRules::new();
+        for &dir in CardinalDirs::iter() {
+            adjacency_rules.allow(0, 0, dir);
+        }
+        // nothing can be to the right of 1
+        for dir in [Up, Down, Left] {
+            adjacency_rules.allow(1, 0, dir);
+        }
+        let mut model = Model::from_seed(adjacency_rules, vec![1.0, 100.0], UVec2::splat(3), 0);
+        model.run().unwrap();
+        for cell in model.iter_cells() {
+            if cell.loc.x < 2 {
+                assert_eq!(cell.collapsed_to, Some(0));
+            }
+        }
+        all_adjacency_rules_satisfied(&model);
+    }
+
+    #[test]
+    fn overlapping_model_reproduces_checkerboard() {
+        use crate::preprocessor::{preprocess, AdjacencyMethod, Config, PatternMethod, Symmetry};
+        let image = image::RgbaImage::from_fn(4, 4, |x, y| {
+            image::Rgba([((x + y) % 2 * 255) as u8, 0, 0, 255])
+        });
+        let config = Config {
+            tile_size: UVec2::splat(2),
+            adjacency_method: AdjacencyMethod::Adjacency,
+            pattern_method: PatternMethod::Overlapping,
+            wrap: false,
+            symmetry: Symmetry::None,
+        };
+        let data = preprocess(image, config);
+        let cell_patterns = data.cell_patterns();
+        let mut model = Model::from_seed(
+            data.adjacency_rules,
+            data.tile_frequencies,
+            UVec2::splat(8),
+            0,
+        );
+        model.run().unwrap();
+        let pixel_at =
+            |loc: UVec2| cell_patterns[model.get_cell(loc).unwrap().collapsed_to.unwrap()][0];
+        for cell in model.iter_cells() {
+            if cell.loc.x > 0 {
+                assert_ne!(pixel_at(cell.loc), pixel_at(cell.loc - UVec2::X));
+            }
+            if cell.loc.y > 0 {
+                assert_ne!(pixel_at(cell.loc), pixel_at(cell.loc - UVec2::Y));
+            }
+        }
+    }
+
+    /// The order the cells would be collapsed in if no tiles were removed
+    fn collapse_order(mut model: Model) -> Vec<UVec2> {
+        let num_cells = model.board.len();
+        return std::iter::from_fn(|| model.get_cell_to_collapse())
+            .take(num_cells)
+            .collect();
+    }
+
+    #[test]
+    fn scanline_collapses_rows_in_order() {
+        use crate::heuristic::Scanline;
+        let dims = UVec2::new(4, 3);
+        let model = simple_model(dims, 0).with_heuristic(Scanline);
+        let rows: Vec<UVec2> = (0..dims.y)
+            .flat_map(|y| (0..dims.x).map(move |x| UVec2::new(x, y)))
+            .collect();
+        assert_eq!(collapse_order(model), rows);
+    }
+
+    #[test]
+    fn distance_from_grows_outwards() {
+        use crate::heuristic::DistanceFrom;
+        let point = UVec2::new(6, 3);
+        let model = simple_model(UVec2::splat(10), 0).with_heuristic(DistanceFrom::new(point));
+        let distances: Vec<f32> = collapse_order(model)
+            .iter()
+            .map(|loc| loc.as_vec2().distance(point.as_vec2()))
+            .collect();
+        assert_eq!(distances[0], 0.0);
+        for pair in distances.windows(2) {
+            // only jittered by less than a cell
+            assert!(pair[1] > pair[0] - 1.0);
+        }
+    }
+
+    #[test]
+    fn min_remaining_values_picks_smallest_domain() {
+        use crate::heuristic::MinRemainingValues;
+        use crate::simple_patterns::DL;
+        let mut model = simple_model(UVec2::splat(6), 0).with_heuristic(MinRemainingValues);
+        model.set_tile(UVec2::new(2, 2), DL).unwrap();
+        let loc = model.get_cell_to_collapse().unwrap();
+        let min = model
+            .iter_cells()
+            .filter(|cell| !cell.collapsed())
+            .map(|cell| cell.num_possible())
+            .min()
+            .unwrap();
+        assert_eq!(model.get_cell(loc).unwrap().num_possible(), min);
+        assert!(min < model.adjacency_table.len());
+    }
+
+    #[test]
+    fn heuristics_solve_the_model() {
+        use crate::heuristic::{DistanceFrom, RandomOrder, Scanline};
+        let models = [
+            simple_model(UVec2::splat(10), 0).with_heuristic(Scanline),
+            simple_model(UVec2::splat(10), 0).with_heuristic(RandomOrder),
+            simple_model(UVec2::splat(10), 0).with_heuristic(DistanceFrom::new(UVec2::splat(5))),
+        ];
+        for mut model in models {
+            model.solve(0, 50).unwrap();
+            all_adjacency_rules_satisfied(&model);
+        }
+    }
+
+    #[test]
+    fn entropy_calculations() {
+        let tile_frequencies = [1.0, 2.0, 3.0, 4.0, 5.0];
+        let calculated_entropy: f32 = 2.149;
+        let mut prob = ProbabilityDict::new(tile_frequencies);
+        assert!((calculated_entropy - prob.entropy()) <= f32::EPSILON);
+        prob.remove(5.0);
+        assert!(prob.total_count == 10.0);
+        let calculated_entropy: f32 = 1.621;
+        assert!((calculated_entropy - prob.entropy()) <= f32::EPSILON);
+    }
+
+    #[test]
+    fn zero_weights_have_finite_entropy() {
+        let mut prob = ProbabilityDict::new([0.0, 0.0]);
+        assert_eq!(prob.entropy(), 0.0);
+        prob.remove(0.0);
+        assert_eq!(prob.entropy(), 0.0);
+
+        let data = construct_simple_patterns();
+        let mut model = Model::from_seed(data.adjacency_rules, vec![0.0; 5], UVec2::splat(6), 0);
+        model.solve(0, 20).unwrap();
+        all_adjacency_rules_satisfied(&model);
+    }
+
+    #[test]
+    fn entropy_heap_is_min_heap() {
+        let mut heap = MinEntropyHeap::new(4);
         let min_entry = EntropyEntry {
             entropy: 0.1,
-            loc: UVec2::X,
+            index: 1,
         };
         let max_entry = EntropyEntry {
             entropy: 0.2,
-            loc: UVec2::Y,
+            index: 2,
         };
         heap.push(min_entry);
         heap.push(max_entry);
         assert!(heap.pop() == Some(min_entry));
     }
+
+    #[test]
+    fn entropy_heap_updates_entries_in_place() {
+        let mut heap = MinEntropyHeap::new(16);
+        for index in 0..16 {
+            heap.push(EntropyEntry {
+                entropy: index as f32,
+                index,
+            });
+        }
+        // lower, raise and re-push entries of cells already in the heap
+        for (entropy, index) in [(-1.0, 15), (100.0, 0), (-1.0, 15)] {
+            heap.push(EntropyEntry { entropy, index });
+        }
+        heap.remove(1);
+        assert_eq!(heap.len(), 15);
+        assert_eq!(heap.pop().unwrap().index, 15);
+        let mut popped = Vec::new();
+        while let Some(entry) = heap.pop() {
+            popped.push(entry.entropy);
+        }
+        assert!(popped.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert_eq!(popped.last(), Some(&100.0));
+        assert_eq!(popped.len(), 14);
+    }
+
+    #[test]
+    fn entropy_heap_holds_one_entry_per_cell() {
+        let mut model = simple_model(UVec2::splat(20), 0);
+        while !model.is_done() {
+            model.step().unwrap();
+            assert!(model.entropy_heap.len() <= model.remaining_uncollapsed as usize);
+        }
+    }
 }
@@ -9,15 +9,16 @@ use winit::window::Window;
 fn main() {
     std::panic::set_hook(Box::new(console_error_panic_hook::hook));
     console_log::init_with_level(log::Level::Info).expect("error initializing logger");
-    // TODO: specifying seed for random weighting of tiles
     // TODO: render preprocessor steps
 }
 
 #[wasm_bindgen]
 pub struct WfcData {
     model: Model,
+    /// the part of each pattern drawn for a cell
     patterns: Vec<Pattern>,
-    tile_size: UVec2,
+    /// the size of the region of the output drawn for each cell
+    cell_size: UVec2,
     output_dimensions: UVec2,
 }
 
@@ -60,8 +61,7 @@ impl WfcWindow {
     pub fn start_event_loop(mut self) {
         let mut cur_model_data: Option<WfcData> = None;
 
-        // TODO: create done method in model
-        let done = |m: &Model| m.remaining_uncollapsed == 0;
+        let done = |m: &Model| m.is_done();
         let mut playing = true;
 
         let mut done_callback: Option<Box<dyn FnOnce()>> = None;
@@ -117,8 +117,15 @@ impl WfcWindow {
                         cb();
                     }
                     if playing && !done(&data.model) {
-                        let updated_cells = data.model.step();
-                        update_frame_buffer(&mut self.pixels, &data, updated_cells);
+                        match data.model.step() {
+                            Ok(updated_cells) => {
+                                update_frame_buffer(&mut self.pixels, &data, updated_cells);
+                            }
+                            Err(err) => {
+                                log::error!("wfc failed: {err}");
+                                playing = false;
+                            }
+                        }
                     }
                     let err = self.pixels.render();
                     if let Err(err) = err {
@@ -138,36 +145,33 @@ fn update_frame_buffer(pixels: &mut Pixels, data: &WfcData, mut updated_cells: V
     let WfcData {
         model,
         patterns,
-        tile_size,
+        cell_size,
         output_dimensions,
     } = data;
-    let tile_size = *tile_size;
+    let tile_size = *cell_size;
 
     let frame = pixels.get_frame_mut();
 
     while let Some(cell_loc) = updated_cells.pop() {
         let cell = model.get_cell(cell_loc).unwrap();
-        // TODO: find more efficient way of determining whether cell
-        // has a single allowed pattern left
-        // this may require some sort of restructure of cell.domain
-        // as currently figuring out if it is the last remaining pattern
-        // requires iterating through all the patterns and cell.collapsed is
-        // not set
 
         // per-pixel weighted average of the allowed patterns for this cell
         let cell_pattern = if let Some(final_pattern) = cell.collapsed_to {
             patterns[final_pattern].to_owned()
+        } else if cell.is_decided() {
+            // a single pattern is left but the cell hasn't been collapsed yet
+            let final_pattern = cell.domain.iter().next().unwrap();
+            patterns[final_pattern].to_owned()
         } else {
             let num_pixels = tile_size.x * tile_size.y;
-            let mut counts = vec![[0; 4]; num_pixels as usize];
-            let allowed_tile_ids = cell.domain.allowed_tile_ids();
+            let mut counts = vec![[0.0; 4]; num_pixels as usize];
 
-            for pattern_id in cell.domain.allowed_tile_ids() {
-                let weight = cell.probability_dict.counts[pattern_id];
+            for pattern_id in cell.domain.iter() {
+                let weight = cell.weight(pattern_id);
                 for (i, px) in patterns[pattern_id].iter().enumerate() {
-                    counts[i][0] += px[0] as usize * weight;
-                    counts[i][1] += px[1] as usize * weight;
-                    counts[i][2] += px[2] as usize * weight;
+                    counts[i][0] += px[0] as f64 * weight;
+                    counts[i][1] += px[1] as f64 * weight;
+                    counts[i][2] += px[2] as f64 * weight;
                     // new_pattern[i][3] += px[3] as usize* weight;
                 }
             }
@@ -213,7 +217,7 @@ pub mod Settings {
     use super::wasm_bindgen;
     use glam::UVec2;
     use serde::Deserialize;
-    use wfc_lib::preprocessor::{AdjacencyMethod, PatternMethod};
+    use wfc_lib::preprocessor::{AdjacencyMethod, PatternMethod, Symmetry};
 
     #[derive(Deserialize, tsify::Tsify)]
     #[serde(rename = "UVec2", remote = "UVec2")]
@@ -237,6 +241,17 @@ pub mod Settings {
         pub output_dimensions: UVec2,
         pub pattern_method: PatternMethod,
         pub adjacency_method: AdjacencyMethod,
+        /// whether the input image is treated as periodic
+        #[serde(default)]
+        pub wrap: bool,
+        /// rotations and reflections of the input patterns to also use
+        #[serde(default)]
+        pub symmetry: Symmetry,
+        /// seed for the model, a random one is picked when not provided. Limited to 32 bits
+        /// so it survives being passed around as a JS number
+        #[serde(default)]
+        #[tsify(optional)]
+        pub seed: Option<u32>,
     }
 
     impl PlayerSettings {
@@ -245,12 +260,16 @@ pub mod Settings {
                 tile_size,
                 pattern_method,
                 adjacency_method,
+                wrap,
+                symmetry,
                 ..
             } = *self;
             return wfc_lib::preprocessor::Config {
                 pattern_method,
                 adjacency_method,
                 tile_size,
+                wrap,
+                symmetry,
             };
         }
     }
@@ -281,15 +300,25 @@ pub fn build_from_json_settings(
     let output_dimensions = settings.output_dimensions.into();
 
     let pp_data = wfc_lib::preprocessor::preprocess(image, pp_settings);
-    let model = Model::new(
-        pp_data.adjacency_rules,
-        pp_data.tile_frequencies,
-        output_dimensions / settings.tile_size,
-    );
+    let patterns = pp_data.cell_patterns();
+    let cell_size = pp_data.cell_size;
+    let model = match settings.seed {
+        Some(seed) => Model::from_seed(
+            pp_data.adjacency_rules,
+            pp_data.tile_frequencies,
+            output_dimensions / cell_size,
+            seed as u64,
+        ),
+        None => Model::new(
+            pp_data.adjacency_rules,
+            pp_data.tile_frequencies,
+            output_dimensions / cell_size,
+        ),
+    };
     return WfcData {
         model,
-        patterns: pp_data.patterns,
-        tile_size: settings.tile_size,
+        patterns,
+        cell_size,
         output_dimensions,
     };
 }
@@ -16,6 +16,8 @@ import type {
     PlayerSettings,
     EdgeMethod,
     AdjacencyMethod,
+    PatternMethod,
+    Symmetry,
 } from "./wfc-web.d.ts";
 
 import type * as WfcNamespace from "./wfc-web.d.ts";
@@ -67,6 +69,8 @@ function wang_preset_settings(em: EdgeMethod) {
         pattern_method: "tiled",
         tile_size: { x: 32, y: 32 },
         output_dimensions: { x: 256, y: 256 },
+        wrap: false,
+        symmetry: "none",
     };
 }
 
@@ -82,6 +86,8 @@ const PRESET_SETTINGS: PresetMap<PlayerSettings> = {
         pattern_method: "tiled",
         tile_size: { x: 3, y: 3 },
         output_dimensions: { x: 60, y: 60 },
+        wrap: false,
+        symmetry: "none",
     },
 };
 
@@ -281,9 +287,15 @@ const PlayerSettingsMenu: FC = () => {
                     <select
                         class="border-2 text-white bg-gray-500 rounded-sm p-1"
                         value={settings.pattern_method}
+                        onChange={(e) =>
+                            setSettings(
+                                "pattern_method",
+                                e.target.value as PatternMethod,
+                            )
+                        }
                     >
                         <option value="tiled">Tiled</option>
-                        {/*TODO: Overlapping optionn*/}
+                        <option value="overlapping">Overlapping</option>
                     </select>
                 </div>
                 <div>
@@ -315,6 +327,34 @@ const PlayerSettingsMenu: FC = () => {
                         </optgroup>
                     </select>
                 </div>
+                <div>
+                    <span class="mr-1">Symmetry</span>
+                    <select
+                        class="border-2 text-white bg-gray-500 rounded-sm p-1"
+                        value={settings.symmetry}
+                        onChange={(e) =>
+                            setSettings(
+                                "symmetry",
+                                e.target.value as Symmetry,
+                            )
+                        }
+                    >
+                        <option value="none">None</option>
+                        <option value="rotation">Rotation</option>
+                        <option value="reflection">Reflection</option>
+                        <option value="all">All</option>
+                    </select>
+                </div>
+                <div>
+                    <span class="mr-1">Wrap Input:</span>
+                    <input
+                        type="checkbox"
+                        checked={settings.wrap}
+                        onChange={(e) =>
+                            setSettings("wrap", e.target.checked)
+                        }
+                    ></input>
+                </div>
             </PlayerSettingsSection>
             <Divider />
             <PlayerSettingsSection title="Model Settings">
@@ -16,12 +16,17 @@ export interface PlayerSettings {
     output_dimensions: UVec2;
     pattern_method: PatternMethod;
     adjacency_method: AdjacencyMethod;
+    wrap: boolean;
+    symmetry: Symmetry;
+    seed?: number;
 }
 
 export interface Config {
     tile_size: UVec2;
     adjacency_method: AdjacencyMethod;
     pattern_method: PatternMethod;
+    wrap: boolean;
+    symmetry: Symmetry;
 }
 
 export interface UVec2 {
@@ -31,6 +36,8 @@ export interface UVec2 {
 
 export type PatternMethod = "overlapping" | "tiled";
 
+export type Symmetry = "none" | "rotation" | "reflection" | "all";
+
 export type AdjacencyMethod = "adjacency" | { edge: EdgeMethod };
 
 export type EdgeMethod = "perfect" | "adjacent" | "flip";